@@ -37,6 +37,35 @@ impl BasinService {
         Ok(streams)
     }
 
+    /// List all live streams with names starting with `prefix`, following
+    /// pagination.
+    pub async fn list_all_streams(&self, prefix: &str) -> Result<Vec<StreamInfo>, ServiceError> {
+        let mut streams = Vec::new();
+        let mut start_after = String::new();
+        loop {
+            let list_streams_req = ListStreamsRequest::new()
+                .with_prefix(prefix)
+                .with_start_after(start_after);
+            let ListStreamsResponse {
+                streams: page,
+                has_more,
+            } = self
+                .client
+                .list_streams(list_streams_req)
+                .await
+                .map_err(|e| ServiceError::new(ServiceErrorContext::ListStreams, e))?;
+
+            start_after = match page.last() {
+                Some(last) if has_more => last.name.clone(),
+                _ => {
+                    streams.extend(page.into_iter().filter(|s| s.deleted_at.is_none()));
+                    return Ok(streams);
+                }
+            };
+            streams.extend(page.into_iter().filter(|s| s.deleted_at.is_none()));
+        }
+    }
+
     pub async fn create_stream(
         &self,
         stream: String,
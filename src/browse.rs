@@ -0,0 +1,490 @@
+//! Interactive terminal browser for the records of a stream.
+//!
+//! Records are fetched a page at a time with unary reads, so the browser can
+//! move backward as easily as forward. Follow mode polls for records past the
+//! current page and keeps the latest page in view.
+
+use std::time::Duration;
+
+use crossterm::event::{Event, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures::StreamExt;
+use ratatui::{
+    layout::{Constraint, Layout},
+    style::{Color, Style, Stylize},
+    text::Line,
+    widgets::{Block, List, ListItem, ListState, Paragraph, Wrap},
+    DefaultTerminal, Frame,
+};
+use s2::types::{ReadOutput, SequencedRecord};
+use tokio::select;
+
+use crate::{
+    error::{S2CliError, ServiceError},
+    formats::{write_escaped, write_hexdump, RecordFormatter},
+    stream::StreamService,
+};
+
+/// How often to poll for new records in follow mode.
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Longest body preview shown in the record list, in characters.
+const PREVIEW_CHARS: usize = 256;
+
+const HELP: &str = "q quit  ↑↓ select  PgUp/PgDn page  g/G head/tail  / search  \
+                    n/N next/prev match  v hex  f follow  J/K scroll";
+
+/// How bodies are shown in the detail pane.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BodyView {
+    /// Pretty printed JSON, plain text, or a hexdump if not UTF-8.
+    Auto,
+    Hex,
+}
+
+fn body_text(body: &[u8], view: BodyView) -> String {
+    if view == BodyView::Auto {
+        if let Ok(json) = serde_json::from_slice::<serde_json::Value>(body) {
+            return serde_json::to_string_pretty(&json).expect("serializable JSON");
+        }
+        if let Ok(text) = std::str::from_utf8(body) {
+            return text.to_string();
+        }
+    }
+    let mut buf = Vec::new();
+    write_hexdump(body, &mut buf);
+    String::from_utf8(buf).expect("ASCII hexdump")
+}
+
+/// Command records described like in text output, e.g. `[s2:trim] ...`.
+fn command_text(record: &SequencedRecord) -> Option<String> {
+    let command = record.as_command_record()?;
+    let mut buf = Vec::new();
+    RecordFormatter::default().write_command(record.seq_num, &command, &mut buf);
+    Some(String::from_utf8_lossy(buf.trim_ascii_end()).into_owned())
+}
+
+fn preview(record: &SequencedRecord) -> String {
+    if let Some(command) = command_text(record) {
+        return command;
+    }
+    let mut buf = Vec::new();
+    write_escaped(&record.body, &mut buf);
+    String::from_utf8_lossy(&buf)
+        .chars()
+        .take(PREVIEW_CHARS)
+        .collect()
+}
+
+/// Whether the body or a header name or value contains `query`.
+fn record_matches(record: &SequencedRecord, query: &str) -> bool {
+    let contains = |bytes: &[u8]| String::from_utf8_lossy(bytes).contains(query);
+    contains(&record.body)
+        || record
+            .headers
+            .iter()
+            .any(|h| contains(&h.name) || contains(&h.value))
+}
+
+enum Mode {
+    Normal,
+    /// Typing a search query.
+    Search(String),
+}
+
+struct Browser {
+    stream_service: StreamService,
+    label: String,
+    page_size: u64,
+    records: Vec<SequencedRecord>,
+    tail: Option<u64>,
+    selected: usize,
+    detail_scroll: u16,
+    view: BodyView,
+    query: Option<String>,
+    mode: Mode,
+    follow: bool,
+    status: String,
+}
+
+impl Browser {
+    /// Read a page of records from `start_seq_num`, or from the first record
+    /// if the stream was trimmed past it.
+    async fn fetch(&self, start_seq_num: u64) -> Result<Vec<SequencedRecord>, ServiceError> {
+        let output = match self
+            .stream_service
+            .read(start_seq_num, self.page_size)
+            .await?
+        {
+            ReadOutput::FirstSeqNum(first_seq_num) => {
+                self.stream_service
+                    .read(first_seq_num, self.page_size)
+                    .await?
+            }
+            output => output,
+        };
+        Ok(match output {
+            ReadOutput::Batch(batch) => batch.records,
+            ReadOutput::FirstSeqNum(_) | ReadOutput::NextSeqNum(_) => Vec::new(),
+        })
+    }
+
+    fn show(&mut self, records: Vec<SequencedRecord>, select_last: bool) {
+        self.selected = if select_last {
+            records.len().saturating_sub(1)
+        } else {
+            0
+        };
+        self.records = records;
+        self.detail_scroll = 0;
+    }
+
+    /// Show the page starting at `seq_num`.
+    async fn goto(&mut self, seq_num: u64) -> Result<(), ServiceError> {
+        let records = self.fetch(seq_num).await?;
+        if records.is_empty() {
+            self.status = format!("No records from seq_num={seq_num}");
+        } else {
+            self.show(records, false);
+        }
+        Ok(())
+    }
+
+    async fn head(&mut self) -> Result<(), ServiceError> {
+        self.goto(0).await
+    }
+
+    /// Show the last page, ending at the tail of the stream.
+    async fn tail(&mut self) -> Result<(), ServiceError> {
+        let tail = self.stream_service.check_tail().await?;
+        self.tail = Some(tail);
+        let records = self.fetch(tail.saturating_sub(self.page_size)).await?;
+        if records.is_empty() {
+            self.status = "Stream is empty".to_string();
+        }
+        self.show(records, true);
+        Ok(())
+    }
+
+    async fn next_page(&mut self) -> Result<(), ServiceError> {
+        let Some(last) = self.records.last() else {
+            return self.tail().await;
+        };
+        let records = self.fetch(last.seq_num + 1).await?;
+        if records.is_empty() {
+            self.status = "At the tail".to_string();
+        } else {
+            self.show(records, false);
+        }
+        Ok(())
+    }
+
+    async fn prev_page(&mut self) -> Result<(), ServiceError> {
+        let Some(first_seq_num) = self.records.first().map(|r| r.seq_num) else {
+            return self.head().await;
+        };
+        let mut records = self
+            .fetch(first_seq_num.saturating_sub(self.page_size))
+            .await?;
+        records.retain(|r| r.seq_num < first_seq_num);
+        if records.is_empty() {
+            self.status = "At the head".to_string();
+        } else {
+            self.show(records, true);
+        }
+        Ok(())
+    }
+
+    /// Add records past the current page, keeping the latest page in view.
+    async fn poll_follow(&mut self) -> Result<(), ServiceError> {
+        let next_seq_num = match self.records.last() {
+            Some(last) => last.seq_num + 1,
+            None => self.stream_service.check_tail().await?,
+        };
+        let records = self.fetch(next_seq_num).await?;
+        if let Some(last) = records.last() {
+            self.tail = Some(last.seq_num + 1);
+            self.records.extend(records);
+            let excess = self.records.len().saturating_sub(self.page_size as usize);
+            self.records.drain(..excess);
+            self.selected = self.records.len() - 1;
+            self.detail_scroll = 0;
+        }
+        Ok(())
+    }
+
+    /// Select the next record on the page matching the query, searching
+    /// backward if `reverse`.
+    fn find(&mut self, reverse: bool) {
+        let Some(query) = &self.query else {
+            self.status = "No search query".to_string();
+            return;
+        };
+        let found = if reverse {
+            (0..self.selected)
+                .rev()
+                .find(|&i| record_matches(&self.records[i], query))
+        } else {
+            (self.selected + 1..self.records.len())
+                .find(|&i| record_matches(&self.records[i], query))
+        };
+        match found {
+            Some(i) => {
+                self.selected = i;
+                self.detail_scroll = 0;
+            }
+            None => self.status = format!("No more matches for {query:?} on this page"),
+        }
+    }
+
+    fn select(&mut self, selected: usize) {
+        self.selected = selected.min(self.records.len().saturating_sub(1));
+        self.detail_scroll = 0;
+    }
+
+    /// Handle a key press, returning whether to keep browsing.
+    async fn handle_key(&mut self, key: KeyEvent) -> Result<bool, ServiceError> {
+        if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+            return Ok(false);
+        }
+        if let Mode::Search(query) = &mut self.mode {
+            match key.code {
+                KeyCode::Enter => {
+                    self.query = Some(std::mem::take(query)).filter(|q| !q.is_empty());
+                    self.mode = Mode::Normal;
+                    if self.query.is_some() && self.records.get(self.selected).is_some() {
+                        // Include the selected record in the first search.
+                        let query = self.query.as_deref().expect("query");
+                        if !record_matches(&self.records[self.selected], query) {
+                            self.find(false);
+                        }
+                    }
+                }
+                KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Backspace => {
+                    query.pop();
+                }
+                KeyCode::Char(c) => query.push(c),
+                _ => {}
+            }
+            return Ok(true);
+        }
+
+        self.status.clear();
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+            KeyCode::Down | KeyCode::Char('j') => self.select(self.selected + 1),
+            KeyCode::Up | KeyCode::Char('k') => self.select(self.selected.saturating_sub(1)),
+            KeyCode::PageDown | KeyCode::Char(' ') => self.next_page().await?,
+            KeyCode::PageUp | KeyCode::Char('b') => {
+                // Paging back stops following, which would pull the page
+                // forward again.
+                self.follow = false;
+                self.prev_page().await?
+            }
+            KeyCode::Home | KeyCode::Char('g') => {
+                self.follow = false;
+                self.head().await?
+            }
+            KeyCode::End | KeyCode::Char('G') => self.tail().await?,
+            KeyCode::Char('/') => self.mode = Mode::Search(String::new()),
+            KeyCode::Char('n') => self.find(false),
+            KeyCode::Char('N') => self.find(true),
+            KeyCode::Char('v') => {
+                self.view = match self.view {
+                    BodyView::Auto => BodyView::Hex,
+                    BodyView::Hex => BodyView::Auto,
+                };
+            }
+            KeyCode::Char('f') => {
+                self.follow = !self.follow;
+                if self.follow {
+                    self.tail().await?;
+                }
+            }
+            KeyCode::Char('J') => self.detail_scroll = self.detail_scroll.saturating_add(1),
+            KeyCode::Char('K') => self.detail_scroll = self.detail_scroll.saturating_sub(1),
+            _ => {}
+        }
+        Ok(true)
+    }
+
+    fn detail_text(&self, record: &SequencedRecord) -> String {
+        if let Some(command) = command_text(record) {
+            return command;
+        }
+        let mut text = String::new();
+        for header in &record.headers {
+            text.push_str(&String::from_utf8_lossy(&header.name));
+            text.push_str(": ");
+            text.push_str(&String::from_utf8_lossy(&header.value));
+            text.push('\n');
+        }
+        if !record.headers.is_empty() {
+            text.push('\n');
+        }
+        text.push_str(&body_text(&record.body, self.view));
+        text
+    }
+
+    fn draw(&self, frame: &mut Frame) {
+        let [title_area, main_area, footer_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [list_area, detail_area] =
+            Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)])
+                .areas(main_area);
+
+        let range = match (self.records.first(), self.records.last()) {
+            (Some(first), Some(last)) => format!("{}..={}", first.seq_num, last.seq_num),
+            _ => "no records".to_string(),
+        };
+        let tail = self
+            .tail
+            .map_or(String::new(), |tail| format!(" of tail {tail}"));
+        let follow = if self.follow { "  [following]" } else { "" };
+        frame.render_widget(
+            Paragraph::new(format!("{}  {range}{tail}{follow}", self.label)).reversed(),
+            title_area,
+        );
+
+        let items: Vec<ListItem> = self
+            .records
+            .iter()
+            .map(|record| {
+                let item = ListItem::new(format!("{:>8}  {}", record.seq_num, preview(record)));
+                match &self.query {
+                    Some(query) if record_matches(record, query) => item.fg(Color::Yellow),
+                    _ => item,
+                }
+            })
+            .collect();
+        let mut list_state = ListState::default()
+            .with_selected(Some(self.selected).filter(|_| !self.records.is_empty()));
+        frame.render_stateful_widget(
+            List::new(items)
+                .block(Block::bordered().title("Records"))
+                .highlight_style(Style::new().reversed()),
+            list_area,
+            &mut list_state,
+        );
+
+        let (detail_title, detail) = match self.records.get(self.selected) {
+            Some(record) => (
+                format!("seq_num={}", record.seq_num),
+                self.detail_text(record),
+            ),
+            None => ("Record".to_string(), String::new()),
+        };
+        frame.render_widget(
+            Paragraph::new(detail)
+                .block(Block::bordered().title(detail_title))
+                .wrap(Wrap { trim: false })
+                .scroll((self.detail_scroll, 0)),
+            detail_area,
+        );
+
+        let footer = match &self.mode {
+            Mode::Search(query) => Line::from(format!("/{query}")),
+            Mode::Normal if !self.status.is_empty() => Line::from(self.status.as_str()).yellow(),
+            Mode::Normal => Line::from(HELP).dim(),
+        };
+        frame.render_widget(Paragraph::new(footer), footer_area);
+    }
+
+    async fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> Result<(), S2CliError> {
+        let mut events = EventStream::new();
+        let mut follow_poll = tokio::time::interval(FOLLOW_POLL_INTERVAL);
+        loop {
+            terminal
+                .draw(|frame| self.draw(frame))
+                .map_err(S2CliError::Terminal)?;
+            let result = select! {
+                event = events.next() => match event {
+                    Some(Ok(Event::Key(key))) if key.kind == KeyEventKind::Press => {
+                        match self.handle_key(key).await {
+                            Ok(true) => Ok(()),
+                            Ok(false) => return Ok(()),
+                            Err(e) => Err(e),
+                        }
+                    }
+                    Some(Ok(_)) => Ok(()),
+                    Some(Err(e)) => return Err(S2CliError::Terminal(e)),
+                    None => return Ok(()),
+                },
+                _ = follow_poll.tick(), if self.follow => self.poll_follow().await,
+            };
+            // Keep browsing after failed reads, which may be transient.
+            if let Err(e) = result {
+                self.status = e.to_string().replace('\n', " ");
+            }
+        }
+    }
+}
+
+/// Browse the stream in a full-screen terminal UI, starting from the page at
+/// `start_seq_num`, or the last page.
+pub async fn run(
+    stream_service: StreamService,
+    label: String,
+    start_seq_num: Option<u64>,
+    page_size: u64,
+) -> Result<(), S2CliError> {
+    let mut browser = Browser {
+        stream_service,
+        label,
+        page_size,
+        records: Vec::new(),
+        tail: None,
+        selected: 0,
+        detail_scroll: 0,
+        view: BodyView::Auto,
+        query: None,
+        mode: Mode::Normal,
+        follow: false,
+        status: String::new(),
+    };
+    // Load the first page before taking over the terminal, so that errors
+    // are reported as usual.
+    match start_seq_num {
+        Some(start_seq_num) => browser.goto(start_seq_num).await?,
+        None => browser.tail().await?,
+    }
+
+    let mut terminal = ratatui::try_init().map_err(S2CliError::Terminal)?;
+    let result = browser.event_loop(&mut terminal).await;
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use s2::types::Header;
+
+    use super::*;
+    use crate::test_util::record;
+
+    #[test]
+    fn test_body_text() {
+        assert_eq!(
+            body_text(br#"{"a":[1]}"#, BodyView::Auto),
+            "{\n  \"a\": [\n    1\n  ]\n}"
+        );
+        assert_eq!(body_text(b"plain", BodyView::Auto), "plain");
+        assert_eq!(
+            body_text(b"\xff", BodyView::Auto),
+            body_text(b"\xff", BodyView::Hex)
+        );
+        assert!(body_text(b"plain", BodyView::Hex).starts_with("00000000  70 6c"));
+    }
+
+    #[test]
+    fn test_record_matches() {
+        let record = record(0, vec![Header::new("tenant", "acme")], "request timeout");
+        assert!(record_matches(&record, "timeout"));
+        assert!(record_matches(&record, "acme"));
+        assert!(!record_matches(&record, "error"));
+    }
+}
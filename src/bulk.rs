@@ -0,0 +1,368 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use colored::*;
+use futures::StreamExt;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use s2::{
+    batching::AppendRecordsBatchingOpts,
+    client::{BasinClient, ClientConfig},
+    types::{AppendRecord, BasinName},
+};
+use serde::Deserialize;
+use tokio::sync::oneshot;
+
+use crate::{
+    error::{S2CliError, ServiceError, ServiceErrorContext},
+    stream::{until_error, StreamService},
+    types::BasinNameAndMaybeStreamUri,
+    RecordsIn,
+};
+
+/// Raw manifest entry as written in a TOML or CSV manifest.
+#[derive(Debug, Deserialize)]
+struct RawManifestEntry {
+    input: PathBuf,
+    uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TomlManifest {
+    append: Vec<RawManifestEntry>,
+}
+
+/// A single input file to append to a stream.
+#[derive(Debug, Clone)]
+pub struct ManifestEntry {
+    pub input: PathBuf,
+    pub basin: BasinName,
+    pub stream: String,
+}
+
+impl ManifestEntry {
+    fn uri(&self) -> String {
+        format!("s2://{}/{}", self.basin, self.stream)
+    }
+}
+
+/// Load a bulk append manifest.
+///
+/// Relative input paths are resolved against the directory of the manifest.
+pub fn load_manifest(path: &Path) -> Result<Vec<ManifestEntry>, S2CliError> {
+    let contents = std::fs::read_to_string(path).map_err(|e| {
+        S2CliError::InvalidManifest(miette::miette!("Failed to read {}: {e}", path.display()))
+    })?;
+
+    let raw_entries = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => {
+            toml::from_str::<TomlManifest>(&contents)
+                .map_err(|e| S2CliError::InvalidManifest(miette::miette!("{e}")))?
+                .append
+        }
+        Some("csv") => parse_csv_manifest(&contents)?,
+        _ => {
+            return Err(S2CliError::InvalidManifest(miette::miette!(
+                help = "Use a manifest with a '.toml' or '.csv' extension",
+                "Unsupported manifest format"
+            )))
+        }
+    };
+
+    let base_dir = path.parent().unwrap_or(Path::new(""));
+
+    raw_entries
+        .into_iter()
+        .enumerate()
+        .map(|(idx, RawManifestEntry { input, uri })| {
+            let BasinNameAndMaybeStreamUri { basin, stream } =
+                BasinNameAndMaybeStreamUri::from_str(&uri).map_err(|e| {
+                    S2CliError::InvalidManifest(
+                        miette::Report::new(e).wrap_err(format!("Entry {}: '{uri}'", idx + 1)),
+                    )
+                })?;
+            let stream = stream.ok_or_else(|| {
+                S2CliError::InvalidManifest(miette::miette!(
+                    help = "Provide the full stream URI like 's2://basin-name/stream/name'",
+                    "Entry {}: missing stream name in '{uri}'",
+                    idx + 1
+                ))
+            })?;
+            Ok(ManifestEntry {
+                input: base_dir.join(input),
+                basin,
+                stream,
+            })
+        })
+        .collect()
+}
+
+/// Parse `input,uri` rows. A leading header row is optional.
+fn parse_csv_manifest(contents: &str) -> Result<Vec<RawManifestEntry>, S2CliError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .comment(Some(b'#'))
+        .trim(csv::Trim::All)
+        .from_reader(contents.as_bytes());
+
+    let mut entries = Vec::new();
+    for (idx, row) in reader.records().enumerate() {
+        let row = row.map_err(|e| S2CliError::InvalidManifest(miette::miette!("{e}")))?;
+        if idx == 0 && row.get(0) == Some("input") && row.get(1) == Some("uri") {
+            continue;
+        }
+        match (row.get(0), row.get(1), row.len()) {
+            (Some(input), Some(uri), 2) => entries.push(RawManifestEntry {
+                input: PathBuf::from(input),
+                uri: uri.to_string(),
+            }),
+            _ => {
+                return Err(S2CliError::InvalidManifest(miette::miette!(
+                    help = "Each row should be of the form 'input,uri'",
+                    "Malformed row {}",
+                    idx + 1
+                )))
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Append all manifest entries, running up to `parallelism` sessions at a time.
+///
+/// Entries for the same stream are appended one after another, in manifest
+/// order, so their records aren't interleaved. Once an entry fails, the rest
+/// for its stream are skipped rather than appended after a gap, while other
+/// streams carry on. An aggregate report is printed once all sessions are
+/// done.
+pub async fn bulk_append(
+    client_config: ClientConfig,
+    entries: Vec<ManifestEntry>,
+    parallelism: usize,
+) -> Result<(), S2CliError> {
+    let total = entries.len();
+
+    let bars = MultiProgress::new();
+    let overall_bar = bars.add(
+        ProgressBar::new(total as u64).with_style(
+            ProgressStyle::default_bar()
+                .template("{prefix:.bold} [{bar:40.green/green}] {pos}/{len} files")
+                .expect("valid template"),
+        ),
+    );
+    overall_bar.set_prefix("bulk append");
+
+    let session_style = ProgressStyle::default_spinner()
+        .template("{spinner} {prefix:.bold} {pos} records {msg}")
+        .expect("valid template");
+
+    let mut basin_clients: HashMap<String, BasinClient> = HashMap::new();
+
+    let sessions = group_by_stream(entries).into_iter().map(|group| {
+        let basin = &group[0].basin;
+        let stream_client = basin_clients
+            .entry(basin.to_string())
+            .or_insert_with(|| BasinClient::new(client_config.clone(), basin.clone()))
+            .stream_client(&group[0].stream);
+        let bars = &bars;
+        let overall_bar = &overall_bar;
+        let session_style = &session_style;
+
+        async move {
+            let mut results = Vec::with_capacity(group.len());
+            let mut failed = false;
+            for entry in group {
+                if failed {
+                    // Appending after a failed file would leave a gap.
+                    overall_bar.inc(1);
+                    results.push((entry, None));
+                    continue;
+                }
+                let bar = bars.insert_before(
+                    overall_bar,
+                    ProgressBar::new_spinner()
+                        .with_prefix(entry.uri())
+                        .with_style(session_style.clone()),
+                );
+                bar.enable_steady_tick(Duration::from_millis(100));
+
+                let result = append_file(
+                    StreamService::new(stream_client.clone()),
+                    &entry.input,
+                    &bar,
+                )
+                .await;
+
+                bar.finish_and_clear();
+                overall_bar.inc(1);
+                failed = result.is_err();
+                results.push((entry, Some(result)));
+            }
+            results
+        }
+    });
+
+    let results: Vec<_> = futures::stream::iter(sessions)
+        .buffer_unordered(parallelism.max(1))
+        .flat_map(futures::stream::iter)
+        .collect()
+        .await;
+
+    overall_bar.finish_and_clear();
+
+    let mut appended_records = 0;
+    let mut failures = Vec::new();
+    let mut skipped = Vec::new();
+    for (entry, result) in results {
+        match result {
+            Some(Ok(records)) => appended_records += records,
+            Some(Err(e)) => failures.push((entry, e)),
+            None => skipped.push(entry),
+        }
+    }
+
+    eprintln!(
+        "{}",
+        format!(
+            "✓ {} of {total} files appended ({appended_records} records)",
+            total - failures.len() - skipped.len()
+        )
+        .green()
+        .bold()
+    );
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+
+    for (entry, e) in &failures {
+        eprintln!(
+            "{} {} ← {}\n  {e}",
+            "✗".red().bold(),
+            entry.uri().bold(),
+            entry.input.display()
+        );
+    }
+    for entry in &skipped {
+        eprintln!(
+            "{} {} ← {}\n  skipped after an earlier file for the stream failed",
+            "-".yellow().bold(),
+            entry.uri().bold(),
+            entry.input.display()
+        );
+    }
+
+    Err(S2CliError::BulkAppend {
+        failed: failures.len(),
+        skipped: skipped.len(),
+        total,
+    })
+}
+
+/// Group entries by stream, keeping manifest order within and across groups.
+fn group_by_stream(entries: Vec<ManifestEntry>) -> Vec<Vec<ManifestEntry>> {
+    let mut groups: Vec<Vec<ManifestEntry>> = Vec::new();
+    let mut group_idx: HashMap<String, usize> = HashMap::new();
+    for entry in entries {
+        let idx = *group_idx.entry(entry.uri()).or_insert_with(|| {
+            groups.push(Vec::new());
+            groups.len() - 1
+        });
+        groups[idx].push(entry);
+    }
+    groups
+}
+
+/// Append the records of `input`, failing if it can't be read in full.
+async fn append_file(
+    stream_service: StreamService,
+    input: &Path,
+    bar: &ProgressBar,
+) -> Result<u64, S2CliError> {
+    let lines = RecordsIn::File(input.to_path_buf())
+        .into_reader()
+        .await
+        .map_err(|e| S2CliError::RecordReaderInit(e.to_string()))?;
+    let (read_error_tx, mut read_error_rx) = oneshot::channel();
+    let append_input_stream = until_error(
+        lines.map(|line| {
+            line.map_err(|e| e.to_string())
+                .and_then(|line| AppendRecord::new(line).map_err(|e| e.to_string()))
+        }),
+        read_error_tx,
+    );
+
+    let mut append_output_stream = stream_service
+        .append_session(append_input_stream, AppendRecordsBatchingOpts::new())
+        .await?;
+
+    let mut appended_records = 0;
+    while let Some(append_result) = append_output_stream.next().await {
+        let append_result =
+            append_result.map_err(|e| ServiceError::new(ServiceErrorContext::AppendSession, e))?;
+        appended_records += append_result.end_seq_num - append_result.start_seq_num;
+        bar.set_position(appended_records);
+        bar.set_message(format!("(next: {})", append_result.next_seq_num));
+    }
+
+    if let Ok(e) = read_error_rx.try_recv() {
+        return Err(S2CliError::RecordRead(format!(
+            "{}: {e} after {appended_records} records",
+            input.display()
+        )));
+    }
+
+    Ok(appended_records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_manifest() {
+        let entries = parse_csv_manifest(
+            "input,uri\n\
+             # comment\n\
+             a.log, s2://basin-name/a\n\
+             dir/b.log,s2://basin-name/b/c\n",
+        )
+        .unwrap();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|e| (e.input.to_str().unwrap(), e.uri.as_str()))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("a.log", "s2://basin-name/a"),
+                ("dir/b.log", "s2://basin-name/b/c")
+            ]
+        );
+
+        assert!(parse_csv_manifest("a.log\n").is_err());
+        assert!(parse_csv_manifest("a.log,s2://basin-name/a,extra\n").is_err());
+    }
+
+    #[test]
+    fn test_group_by_stream() {
+        let entry = |input: &str, stream: &str| ManifestEntry {
+            input: PathBuf::from(input),
+            basin: "basin-name".parse().unwrap(),
+            stream: stream.to_string(),
+        };
+        let groups = group_by_stream(vec![
+            entry("a1.log", "a"),
+            entry("b.log", "b"),
+            entry("a2.log", "a"),
+        ]);
+        let groups: Vec<Vec<_>> = groups
+            .iter()
+            .map(|group| group.iter().map(|e| e.input.to_str().unwrap()).collect())
+            .collect();
+        assert_eq!(groups, vec![vec!["a1.log", "a2.log"], vec!["b.log"]]);
+    }
+}
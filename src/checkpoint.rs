@@ -0,0 +1,141 @@
+//! Durable read positions for long-lived consumers.
+
+use std::{
+    fs::{File, OpenOptions, TryLockError},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::S2CliError;
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+struct CheckpointFile {
+    basin: String,
+    stream: String,
+    next_seq_num: u64,
+}
+
+/// A checkpoint file recording the next sequence number to read from a stream.
+///
+/// The checkpoint is locked through a `.lock` file next to it for as long as
+/// this is alive, so that two consumers can't share it.
+pub struct Checkpoint {
+    path: PathBuf,
+    basin: String,
+    stream: String,
+    _lock: File,
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    path.into()
+}
+
+impl Checkpoint {
+    /// Lock the checkpoint at `path` and load the position saved in it, if
+    /// the file exists.
+    pub fn open(
+        path: &Path,
+        basin: String,
+        stream: String,
+    ) -> Result<(Self, Option<u64>), S2CliError> {
+        let checkpoint_err = |reason: String| S2CliError::Checkpoint {
+            path: path.display().to_string(),
+            reason,
+        };
+
+        let lock = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(with_suffix(path, ".lock"))
+            .map_err(|e| checkpoint_err(format!("failed to open lock file: {e}")))?;
+        match lock.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                return Err(checkpoint_err("in use by another consumer".to_string()))
+            }
+            Err(TryLockError::Error(e)) => {
+                return Err(checkpoint_err(format!("failed to lock: {e}")))
+            }
+        }
+
+        let next_seq_num = match std::fs::read_to_string(path) {
+            Ok(contents) => {
+                let saved: CheckpointFile =
+                    serde_json::from_str(&contents).map_err(|e| checkpoint_err(e.to_string()))?;
+                if saved.basin != basin || saved.stream != stream {
+                    return Err(checkpoint_err(format!(
+                        "belongs to s2://{}/{}",
+                        saved.basin, saved.stream
+                    )));
+                }
+                Some(saved.next_seq_num)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(checkpoint_err(e.to_string())),
+        };
+
+        Ok((
+            Self {
+                path: path.to_path_buf(),
+                basin,
+                stream,
+                _lock: lock,
+            },
+            next_seq_num,
+        ))
+    }
+
+    /// Atomically replace the saved position, by writing to a temporary file
+    /// and renaming it over the checkpoint.
+    pub fn save(&self, next_seq_num: u64) -> Result<(), S2CliError> {
+        let contents = serde_json::to_vec(&CheckpointFile {
+            basin: self.basin.clone(),
+            stream: self.stream.clone(),
+            next_seq_num,
+        })
+        .expect("serializable checkpoint");
+
+        let tmp_path = with_suffix(&self.path, ".tmp");
+        File::create(&tmp_path)
+            .and_then(|mut file| {
+                file.write_all(&contents)?;
+                file.sync_all()
+            })
+            .and_then(|_| std::fs::rename(&tmp_path, &self.path))
+            .map_err(|e| S2CliError::Checkpoint {
+                path: self.path.display().to_string(),
+                reason: format!("failed to save: {e}"),
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_checkpoint_save_resume() {
+        let dir = TempDir::new("checkpoint");
+        let path = dir.join("consumer.json");
+
+        let (checkpoint, next) = Checkpoint::open(&path, "basin".into(), "s".into()).unwrap();
+        assert_eq!(next, None);
+        checkpoint.save(42).unwrap();
+
+        assert!(matches!(
+            Checkpoint::open(&path, "basin".into(), "s".into()),
+            Err(S2CliError::Checkpoint { reason, .. }) if reason.contains("another consumer")
+        ));
+        drop(checkpoint);
+
+        assert!(Checkpoint::open(&path, "basin".into(), "other".into()).is_err());
+        let (_checkpoint, next) = Checkpoint::open(&path, "basin".into(), "s".into()).unwrap();
+        assert_eq!(next, Some(42));
+    }
+}
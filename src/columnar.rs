@@ -0,0 +1,524 @@
+//! Columnar export of records in a read session, as Parquet or Arrow IPC files.
+//!
+//! Records are buffered into chunks, converted to Arrow record batches and
+//! written out on the blocking thread pool. Files are written to a `.partial`
+//! path and only renamed into place once complete, since both formats need a
+//! footer to be readable.
+
+use std::{fs::File, io::BufWriter, path::PathBuf, sync::Arc};
+
+use arrow::{
+    array::{ArrayRef, BinaryArray, ListArray, StructArray, UInt64Array},
+    buffer::OffsetBuffer,
+    datatypes::{DataType, Field, Fields, Schema, SchemaRef},
+    error::ArrowError,
+    ipc::writer::FileWriter,
+    json::{reader::infer_json_schema_from_iterator, ReaderBuilder},
+    record_batch::RecordBatch,
+};
+use clap::Args;
+use colored::Colorize;
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use s2::types::SequencedRecord;
+
+use crate::error::S2CliError;
+
+/// Records converted to a record batch at a time.
+const CHUNK_RECORDS: usize = 8192;
+
+/// Buffered record bytes that also complete a chunk.
+const CHUNK_BYTES: usize = 8 * 1024 * 1024;
+
+/// Encoded size at which a Parquet row group is closed, keeping memory use
+/// bounded for large records.
+const ROW_GROUP_BYTES: usize = 64 * 1024 * 1024;
+
+/// Row count at which a Parquet row group is closed.
+const ROW_GROUP_RECORDS: usize = 1024 * 1024;
+
+/// Columns every export has, which flattened JSON fields can't replace.
+const BASE_COLUMNS: [&str; 3] = ["seq_num", "headers", "body"];
+
+#[derive(Args, Debug, Clone)]
+#[command(next_help_heading = "Columnar export")]
+pub struct ColumnarArgs {
+    /// Add a typed column for each top-level field of JSON record bodies to
+    /// Parquet and Arrow outputs.
+    ///
+    /// Column types are inferred from a sample of the first records. Fields of
+    /// records that aren't JSON objects or don't match the inferred types are
+    /// left null.
+    #[arg(long)]
+    pub flatten_json: bool,
+
+    /// Number of records to infer the types of flattened JSON fields from.
+    #[arg(
+        long,
+        value_name = "RECORDS",
+        default_value_t = 1000,
+        requires = "flatten_json"
+    )]
+    pub flatten_sample: usize,
+}
+
+/// File format of a columnar export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnarFormat {
+    Parquet,
+    ArrowIpc,
+}
+
+enum FormatWriter {
+    Parquet(ArrowWriter<File>),
+    ArrowIpc(FileWriter<BufWriter<File>>),
+}
+
+impl FormatWriter {
+    fn write(&mut self, batch: &RecordBatch) -> Result<(), String> {
+        match self {
+            Self::Parquet(writer) => {
+                writer.write(batch).map_err(|e| e.to_string())?;
+                if writer.in_progress_size() >= ROW_GROUP_BYTES {
+                    writer.flush().map_err(|e| e.to_string())?;
+                }
+                Ok(())
+            }
+            Self::ArrowIpc(writer) => writer.write(batch).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self {
+            Self::Parquet(writer) => writer.close().map(|_| ()).map_err(|e| e.to_string()),
+            Self::ArrowIpc(mut writer) => writer.finish().map_err(|e| e.to_string()),
+        }
+    }
+}
+
+/// Output file, along with its schema once the first chunk is written.
+struct ColumnarFile {
+    format: ColumnarFormat,
+    path: PathBuf,
+    partial_path: PathBuf,
+    file: Option<File>,
+    flatten_sample: Option<usize>,
+    // Schema of flattened JSON fields, if flattening.
+    json_schema: Option<SchemaRef>,
+    writer: Option<FormatWriter>,
+}
+
+/// Writer that exports records to a Parquet or Arrow IPC file.
+///
+/// Records get `seq_num`, `headers` and `body` columns, with headers as a list
+/// of binary name and value pairs and bodies as binary.
+pub struct ColumnarWriter {
+    // Taken while a chunk is written on the blocking thread pool.
+    file: Option<Box<ColumnarFile>>,
+    pending: Vec<SequencedRecord>,
+    pending_bytes: usize,
+    chunk_records: usize,
+    first_seq_num: Option<u64>,
+}
+
+impl ColumnarWriter {
+    pub fn open(
+        format: ColumnarFormat,
+        path: PathBuf,
+        args: &ColumnarArgs,
+    ) -> Result<Self, S2CliError> {
+        let mut partial_path = path.as_os_str().to_owned();
+        partial_path.push(".partial");
+        let partial_path = PathBuf::from(partial_path);
+        let file =
+            File::create(&partial_path).map_err(|e| S2CliError::file_write(&partial_path, e))?;
+
+        let flatten_sample = args.flatten_json.then_some(args.flatten_sample.max(1));
+        Ok(Self {
+            file: Some(Box::new(ColumnarFile {
+                format,
+                path,
+                partial_path,
+                file: Some(file),
+                flatten_sample,
+                json_schema: None,
+                writer: None,
+            })),
+            pending: Vec::new(),
+            pending_bytes: 0,
+            // The first chunk has to hold the whole sample.
+            chunk_records: flatten_sample.map_or(CHUNK_RECORDS, |n| n.max(CHUNK_RECORDS)),
+            first_seq_num: None,
+        })
+    }
+
+    /// Sequence number of the first record written, until the file is
+    /// finished, since none of them are readable before that.
+    pub fn pending_seq_num(&self) -> Option<u64> {
+        self.first_seq_num
+    }
+
+    pub async fn write_batch<'a>(
+        &mut self,
+        records: impl IntoIterator<Item = &'a SequencedRecord>,
+    ) -> Result<(), S2CliError> {
+        for record in records {
+            self.first_seq_num.get_or_insert(record.seq_num);
+            self.pending_bytes += record.body.len();
+            self.pending.push(record.clone());
+        }
+        if self.pending.len() >= self.chunk_records || self.pending_bytes >= CHUNK_BYTES {
+            self.write_pending(false).await?;
+        }
+        Ok(())
+    }
+
+    /// Write any buffered records, and finish the file so it is readable.
+    pub async fn close(&mut self) -> Result<(), S2CliError> {
+        self.write_pending(true).await
+    }
+
+    /// Stop writing without finishing the file, which is left at its
+    /// `.partial` path.
+    pub fn abandon(&mut self) {
+        self.pending.clear();
+        if let Some(file) = self.file.take() {
+            eprintln!(
+                "{}",
+                format!(
+                    "Read incomplete, leaving unfinished {}",
+                    file.partial_path.display()
+                )
+                .yellow()
+                .bold()
+            );
+        }
+    }
+
+    async fn write_pending(&mut self, finish: bool) -> Result<(), S2CliError> {
+        let Some(mut file) = self.file.take() else {
+            // Already closed.
+            return Ok(());
+        };
+        let records = std::mem::take(&mut self.pending);
+        self.pending_bytes = 0;
+        self.chunk_records = CHUNK_RECORDS;
+
+        let (file, result) =
+            tokio::task::spawn_blocking(move || {
+                let result = file.write_chunk(&records).and_then(|()| {
+                    if finish {
+                        file.finish()
+                    } else {
+                        Ok(())
+                    }
+                });
+                (file, result)
+            })
+            .await
+            .expect("columnar write task");
+
+        let result = result.map_err(|e| S2CliError::file_write(&file.path, e));
+        if !finish {
+            self.file = Some(file);
+        } else if result.is_ok() {
+            self.first_seq_num = None;
+            eprintln!("Wrote records to {}", file.path.display());
+        }
+        result
+    }
+}
+
+impl Drop for ColumnarWriter {
+    fn drop(&mut self) {
+        // Reached if the read fails before the file is finished.
+        self.abandon();
+    }
+}
+
+impl ColumnarFile {
+    fn write_chunk(&mut self, records: &[SequencedRecord]) -> Result<(), String> {
+        if records.is_empty() {
+            return Ok(());
+        }
+        if self.writer.is_none() {
+            if let Some(sample) = self.flatten_sample {
+                self.json_schema = Some(infer_schema(&records[..records.len().min(sample)]));
+            }
+            self.open_writer()?;
+        }
+        let batch = self.record_batch(records).map_err(|e| e.to_string())?;
+        self.writer.as_mut().expect("open writer").write(&batch)
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        if self.writer.is_none() {
+            // No records, but the file should still be readable.
+            self.open_writer()?;
+        }
+        self.writer.take().expect("open writer").finish()?;
+        std::fs::rename(&self.partial_path, &self.path).map_err(|e| e.to_string())
+    }
+
+    fn schema(&self) -> SchemaRef {
+        let mut fields = vec![
+            Field::new("seq_num", DataType::UInt64, false),
+            Field::new("headers", headers_type(), false),
+            Field::new("body", DataType::Binary, false),
+        ];
+        if let Some(json_schema) = &self.json_schema {
+            fields.extend(json_schema.fields().iter().map(|f| f.as_ref().clone()));
+        }
+        Arc::new(Schema::new(fields))
+    }
+
+    fn open_writer(&mut self) -> Result<(), String> {
+        let file = self.file.take().expect("output file");
+        let schema = self.schema();
+        self.writer = Some(match self.format {
+            ColumnarFormat::Parquet => {
+                let props = WriterProperties::builder()
+                    .set_compression(Compression::SNAPPY)
+                    .set_max_row_group_size(ROW_GROUP_RECORDS)
+                    .build();
+                FormatWriter::Parquet(
+                    ArrowWriter::try_new(file, schema, Some(props)).map_err(|e| e.to_string())?,
+                )
+            }
+            ColumnarFormat::ArrowIpc => FormatWriter::ArrowIpc(
+                FileWriter::try_new_buffered(file, &schema).map_err(|e| e.to_string())?,
+            ),
+        });
+        Ok(())
+    }
+
+    fn record_batch(&self, records: &[SequencedRecord]) -> Result<RecordBatch, ArrowError> {
+        let seq_nums = UInt64Array::from_iter_values(records.iter().map(|r| r.seq_num));
+        let headers = headers_array(records);
+        let bodies = BinaryArray::from_iter_values(records.iter().map(|r| &r.body[..]));
+
+        let mut columns: Vec<ArrayRef> =
+            vec![Arc::new(seq_nums), Arc::new(headers), Arc::new(bodies)];
+        if let Some(json_schema) = &self.json_schema {
+            columns.extend(flatten_json(json_schema, records)?);
+        }
+        RecordBatch::try_new(self.schema(), columns)
+    }
+}
+
+fn header_fields() -> Fields {
+    Fields::from(vec![
+        Field::new("name", DataType::Binary, false),
+        Field::new("value", DataType::Binary, false),
+    ])
+}
+
+fn headers_type() -> DataType {
+    DataType::new_list(DataType::Struct(header_fields()), false)
+}
+
+fn headers_array(records: &[SequencedRecord]) -> ListArray {
+    let headers: Vec<_> = records.iter().flat_map(|r| &r.headers).collect();
+    let names = BinaryArray::from_iter_values(headers.iter().map(|h| &h.name[..]));
+    let values = BinaryArray::from_iter_values(headers.iter().map(|h| &h.value[..]));
+    let pairs = StructArray::new(
+        header_fields(),
+        vec![Arc::new(names), Arc::new(values)],
+        None,
+    );
+    ListArray::new(
+        Arc::new(Field::new_list_field(
+            DataType::Struct(header_fields()),
+            false,
+        )),
+        OffsetBuffer::from_lengths(records.iter().map(|r| r.headers.len())),
+        Arc::new(pairs),
+        None,
+    )
+}
+
+/// Bodies parsed as JSON objects, with anything else as an empty object.
+fn json_objects(records: &[SequencedRecord]) -> Vec<serde_json::Value> {
+    records
+        .iter()
+        .map(|r| match serde_json::from_slice(&r.body) {
+            Ok(value @ serde_json::Value::Object(_)) => value,
+            _ => serde_json::Value::Object(Default::default()),
+        })
+        .collect()
+}
+
+/// Infer the schema of top-level fields of JSON bodies, leaving out fields
+/// named like the base columns, and fields whose types conflict, like an
+/// object in one record and a number in another.
+fn infer_schema(sample: &[SequencedRecord]) -> SchemaRef {
+    let objects = json_objects(sample);
+    let mut names: Vec<&String> = Vec::new();
+    for object in &objects {
+        for name in object.as_object().into_iter().flat_map(|o| o.keys()) {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+
+    let mut fields = Vec::with_capacity(names.len());
+    for name in names {
+        if BASE_COLUMNS.contains(&name.as_str()) {
+            eprintln!("Not flattening JSON field {name:?}, named like a column");
+            continue;
+        }
+        let values = objects.iter().filter_map(|object| {
+            let value = object.get(name)?;
+            Some(Ok(serde_json::json!({ name: value })))
+        });
+        match infer_json_schema_from_iterator(values) {
+            Ok(schema) => fields.push(schema.field(0).clone()),
+            Err(e) => eprintln!("Not flattening JSON field {name:?}, with conflicting types: {e}"),
+        }
+    }
+    Arc::new(Schema::new(fields))
+}
+
+fn decode_json(
+    json_schema: &SchemaRef,
+    values: &[serde_json::Value],
+) -> Result<RecordBatch, ArrowError> {
+    let mut decoder = ReaderBuilder::new(json_schema.clone())
+        .with_coerce_primitive(true)
+        .build_decoder()?;
+    decoder.serialize(values)?;
+    Ok(decoder
+        .flush()?
+        .unwrap_or_else(|| RecordBatch::new_empty(json_schema.clone())))
+}
+
+/// Columns of flattened JSON fields, one per field of the schema.
+fn flatten_json(
+    json_schema: &SchemaRef,
+    records: &[SequencedRecord],
+) -> Result<Vec<ArrayRef>, ArrowError> {
+    let mut values = json_objects(records);
+    let batch = match decode_json(json_schema, &values) {
+        Ok(batch) => batch,
+        Err(_) => {
+            // Find the records that don't match the schema, and leave their
+            // fields null.
+            for value in &mut values {
+                if decode_json(json_schema, std::slice::from_ref(value)).is_err() {
+                    *value = serde_json::Value::Object(Default::default());
+                }
+            }
+            decode_json(json_schema, &values)?
+        }
+    };
+    Ok(batch.columns().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::{
+        array::{Array, AsArray},
+        datatypes::Int64Type,
+        ipc::reader::FileReader,
+    };
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use s2::types::Header;
+
+    use super::*;
+    use crate::test_util::{record, TempDir};
+
+    #[test]
+    fn test_infer_schema() {
+        let schema = infer_schema(&[
+            record(
+                0,
+                vec![Header::new("host", "a")],
+                br#"{"a": 1, "b": "x", "seq_num": 1}"#,
+            ),
+            record(
+                1,
+                vec![Header::new("host", "a")],
+                br#"{"a": {"b": 1}, "c": true}"#,
+            ),
+        ]);
+        let field_names: Vec<_> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(field_names, ["b", "c"]);
+    }
+
+    #[tokio::test]
+    async fn test_columnar_export() {
+        let dir = TempDir::new("columnar");
+        let path = dir.join("records.arrow");
+
+        let args = ColumnarArgs {
+            flatten_json: true,
+            flatten_sample: 2,
+        };
+        let mut writer =
+            ColumnarWriter::open(ColumnarFormat::ArrowIpc, path.clone(), &args).unwrap();
+        writer
+            .write_batch(&[
+                record(
+                    0,
+                    vec![Header::new("host", "a")],
+                    br#"{"n": 1, "body": "x"}"#,
+                ),
+                record(1, vec![Header::new("host", "a")], br#"{"n": 2}"#),
+                record(2, vec![Header::new("host", "a")], br#"{"n": "three"}"#),
+                record(3, vec![Header::new("host", "a")], b"not json"),
+            ])
+            .await
+            .unwrap();
+        writer.close().await.unwrap();
+
+        let reader = FileReader::try_new(File::open(&path).unwrap(), None).unwrap();
+        let field_names: Vec<_> = reader
+            .schema()
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect();
+        assert_eq!(field_names, ["seq_num", "headers", "body", "n"]);
+
+        let batches: Vec<_> = reader.map(Result::unwrap).collect();
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 4);
+        let n = batch.column(3).as_primitive::<Int64Type>();
+        assert_eq!((n.value(0), n.value(1)), (1, 2));
+        assert!(n.is_null(2) && n.is_null(3));
+        assert_eq!(batch.column(2).as_binary::<i32>().value(3), b"not json");
+        assert_eq!(batch.column(1).as_list::<i32>().value_length(0), 1);
+
+        let path = dir.join("records.parquet");
+        let args = ColumnarArgs {
+            flatten_json: false,
+            flatten_sample: 1000,
+        };
+        let mut writer =
+            ColumnarWriter::open(ColumnarFormat::Parquet, path.clone(), &args).unwrap();
+        writer
+            .write_batch(&[record(7, vec![Header::new("host", "a")], b"hello")])
+            .await
+            .unwrap();
+        assert_eq!(writer.pending_seq_num(), Some(7));
+        writer.close().await.unwrap();
+        assert_eq!(writer.pending_seq_num(), None);
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap()).unwrap();
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 1);
+        assert_eq!(reader.schema().fields().len(), 3);
+
+        let path = dir.join("aborted.parquet");
+        let mut writer =
+            ColumnarWriter::open(ColumnarFormat::Parquet, path.clone(), &args).unwrap();
+        writer
+            .write_batch(&[record(9, vec![], b"hello")])
+            .await
+            .unwrap();
+        writer.abandon();
+        assert_eq!(writer.pending_seq_num(), Some(9));
+        writer.close().await.unwrap();
+        assert!(!path.exists());
+        assert!(dir.join("aborted.parquet.partial").exists());
+    }
+}
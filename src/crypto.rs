@@ -0,0 +1,207 @@
+//! Client-side envelope encryption of record bodies.
+//!
+//! Bodies are sealed with XChaCha20-Poly1305 under a named key. The key id is
+//! carried in a record header so that readers can pick the right key from a
+//! set of keys, which allows keys to be rotated.
+
+use std::{collections::HashMap, path::Path};
+
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use s2::types::{AppendRecord, Header, SequencedRecord};
+use serde::Deserialize;
+
+use crate::error::S2CliError;
+
+/// Name of the header holding the id of the key a record body is sealed with.
+pub const KEY_ID_HEADER: &[u8] = b"s2-key-id";
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+#[derive(Debug, Deserialize)]
+struct KeyFile {
+    key_id: String,
+    key: String,
+}
+
+/// A named encryption key.
+pub struct RecordKey {
+    id: String,
+    cipher: XChaCha20Poly1305,
+}
+
+impl RecordKey {
+    /// Load a key from a TOML file with `key_id` and a hex encoded 32 byte `key`.
+    pub fn load(path: &Path) -> Result<Self, S2CliError> {
+        let key_file_err = |reason: String| S2CliError::KeyFile {
+            path: path.display().to_string(),
+            reason,
+        };
+
+        let contents = std::fs::read_to_string(path).map_err(|e| key_file_err(e.to_string()))?;
+        let KeyFile { key_id, key } =
+            toml::from_str(&contents).map_err(|e| key_file_err(e.message().to_string()))?;
+
+        if key_id.is_empty() {
+            return Err(key_file_err("empty key_id".to_string()));
+        }
+
+        let key = base16ct::mixed::decode_vec(key.trim())
+            .ok()
+            .filter(|key| key.len() == KEY_LEN)
+            .ok_or_else(|| key_file_err(format!("key must be {KEY_LEN} bytes in hex")))?;
+
+        Ok(Self {
+            id: key_id,
+            cipher: XChaCha20Poly1305::new_from_slice(&key).expect("valid key length"),
+        })
+    }
+
+    /// Seal the record body, and tag the record with the key id.
+    ///
+    /// The sealed body is the random nonce followed by the ciphertext. The key
+    /// id is authenticated as associated data.
+    pub fn encrypt(&self, record: AppendRecord) -> Result<AppendRecord, String> {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: record.body(),
+                    aad: self.id.as_bytes(),
+                },
+            )
+            .map_err(|_| "encryption failed".to_string())?;
+
+        let mut body = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        body.extend_from_slice(&nonce);
+        body.extend_from_slice(&ciphertext);
+
+        let mut headers = record.headers().to_vec();
+        headers.push(Header::new(KEY_ID_HEADER, self.id.clone()));
+
+        AppendRecord::new(body)
+            .and_then(|record| record.with_headers(headers))
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DecryptError {
+    #[error("unknown key id '{0}'")]
+    UnknownKeyId(String),
+
+    #[error("failed to decrypt with key id '{0}'")]
+    Invalid(String),
+}
+
+/// Set of keys available for decryption, indexed by key id.
+#[derive(Default)]
+pub struct RecordKeyring {
+    keys: HashMap<String, RecordKey>,
+}
+
+impl RecordKeyring {
+    pub fn load(paths: &[impl AsRef<Path>]) -> Result<Self, S2CliError> {
+        let mut keys = HashMap::new();
+        for path in paths {
+            let key = RecordKey::load(path.as_ref())?;
+            keys.insert(key.id.clone(), key);
+        }
+        Ok(Self { keys })
+    }
+
+    /// Open a sealed record body, and strip the key id header.
+    ///
+    /// Records without a key id header are returned as is.
+    pub fn decrypt(&self, mut record: SequencedRecord) -> Result<SequencedRecord, DecryptError> {
+        let Some(idx) = record
+            .headers
+            .iter()
+            .position(|h| h.name.as_ref() == KEY_ID_HEADER)
+        else {
+            return Ok(record);
+        };
+
+        let key_id = String::from_utf8_lossy(&record.headers[idx].value).into_owned();
+        let key = self
+            .keys
+            .get(&key_id)
+            .ok_or_else(|| DecryptError::UnknownKeyId(key_id.clone()))?;
+
+        if record.body.len() < NONCE_LEN {
+            return Err(DecryptError::Invalid(key_id));
+        }
+        let (nonce, ciphertext) = record.body.split_at(NONCE_LEN);
+        let plaintext = key
+            .cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: key_id.as_bytes(),
+                },
+            )
+            .map_err(|_| DecryptError::Invalid(key_id))?;
+
+        record.headers.remove(idx);
+        record.body = plaintext.into();
+        Ok(record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(id: &str, byte: u8) -> RecordKey {
+        RecordKey {
+            id: id.to_string(),
+            cipher: XChaCha20Poly1305::new_from_slice(&[byte; KEY_LEN]).unwrap(),
+        }
+    }
+
+    fn sequenced(record: AppendRecord) -> SequencedRecord {
+        let parts = record.into_parts();
+        SequencedRecord {
+            seq_num: 0,
+            headers: parts.headers,
+            body: parts.body,
+        }
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_roundtrip() {
+        let record = AppendRecord::new("secret")
+            .unwrap()
+            .with_headers(vec![Header::new("host", "a")])
+            .unwrap();
+        let sealed = sequenced(key("k1", 1).encrypt(record).unwrap());
+        assert_ne!(sealed.body.as_ref(), b"secret");
+
+        let mut keyring = RecordKeyring::default();
+        assert!(matches!(
+            keyring.decrypt(sealed.clone()),
+            Err(DecryptError::UnknownKeyId(id)) if id == "k1"
+        ));
+
+        keyring.keys.insert("k1".to_string(), key("k1", 2));
+        assert!(matches!(
+            keyring.decrypt(sealed.clone()),
+            Err(DecryptError::Invalid(_))
+        ));
+
+        keyring.keys.insert("k1".to_string(), key("k1", 1));
+        keyring.keys.insert("k0".to_string(), key("k0", 0));
+        let opened = keyring.decrypt(sealed).unwrap();
+        assert_eq!(opened.body.as_ref(), b"secret");
+        assert_eq!(opened.headers, vec![Header::new("host", "a")]);
+
+        let plain = sequenced(AppendRecord::new("plain").unwrap());
+        assert_eq!(keyring.decrypt(plain).unwrap().body.as_ref(), b"plain");
+    }
+}
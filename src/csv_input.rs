@@ -0,0 +1,342 @@
+//! CSV/TSV input for appends, mapping columns to record body and headers.
+
+use std::str::FromStr;
+
+use clap::{Args, ValueEnum};
+use colored::Colorize;
+use s2::types::{AppendRecord, Header};
+use tokio::sync::{mpsc, oneshot};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{error::S2CliError, RecordsIn};
+
+/// A column in a CSV row, specified by header name or zero-based index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CsvColumn {
+    Index(usize),
+    Name(String),
+}
+
+impl FromStr for CsvColumn {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.parse::<usize>() {
+            Ok(idx) => Self::Index(idx),
+            Err(_) => Self::Name(s.to_string()),
+        })
+    }
+}
+
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvHeaderRow {
+    /// Treat the first row as a header row if it looks like one.
+    Auto,
+    /// The first row names the columns.
+    Present,
+    /// The first row is data.
+    Absent,
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(next_help_heading = "CSV/TSV input")]
+pub struct CsvInputArgs {
+    /// Column (name or zero-based index) that becomes the record body.
+    #[arg(long, value_name = "COLUMN", default_value = "0")]
+    pub csv_body_column: CsvColumn,
+
+    /// Column (name or zero-based index) to add as a record header.
+    /// The header is named after the column.
+    /// May be specified multiple times.
+    #[arg(long, value_name = "COLUMN")]
+    pub csv_header_column: Vec<CsvColumn>,
+
+    /// Whether the first row names the columns.
+    ///
+    /// In auto mode, the first row is treated as a header row if columns are
+    /// selected by name, or if all its fields are unique, non-empty and
+    /// non-numeric, which is reported on stderr.
+    #[arg(long, value_enum, default_value_t = CsvHeaderRow::Auto)]
+    pub csv_header_row: CsvHeaderRow,
+
+    /// Field delimiter. Defaults to ',' for CSV and a tab for TSV.
+    #[arg(long, value_name = "CHAR")]
+    pub csv_delimiter: Option<char>,
+
+    /// Quote character.
+    #[arg(long, value_name = "CHAR", default_value_t = '"')]
+    pub csv_quote: char,
+
+    /// Escape character for quotes within quoted fields.
+    /// By default, quotes are escaped by doubling them.
+    #[arg(long, value_name = "CHAR")]
+    pub csv_escape: Option<char>,
+
+    /// Disable quoting, treating quote characters as regular data.
+    #[arg(long)]
+    pub csv_no_quoting: bool,
+}
+
+fn ascii_byte(c: char, what: &str) -> Result<u8, String> {
+    if c.is_ascii() {
+        Ok(c as u8)
+    } else {
+        Err(format!(
+            "{what} must be a single ASCII character, got '{c}'"
+        ))
+    }
+}
+
+impl CsvInputArgs {
+    fn reader_builder(&self, default_delimiter: u8) -> Result<csv::ReaderBuilder, String> {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .has_headers(false)
+            .flexible(true)
+            .quoting(!self.csv_no_quoting)
+            .quote(ascii_byte(self.csv_quote, "Quote")?)
+            .delimiter(match self.csv_delimiter {
+                Some(c) => ascii_byte(c, "Delimiter")?,
+                None => default_delimiter,
+            });
+        if let Some(c) = self.csv_escape {
+            builder
+                .escape(Some(ascii_byte(c, "Escape")?))
+                .double_quote(false);
+        }
+        Ok(builder)
+    }
+
+    fn selects_by_name(&self) -> bool {
+        std::iter::once(&self.csv_body_column)
+            .chain(self.csv_header_column.iter())
+            .any(|col| matches!(col, CsvColumn::Name(_)))
+    }
+}
+
+/// Heuristic for whether the first row names columns rather than holding data.
+fn looks_like_header_row(row: &csv::ByteRecord) -> bool {
+    let mut seen = std::collections::HashSet::new();
+    row.iter().all(|field| {
+        let field = String::from_utf8_lossy(field);
+        let field = field.trim();
+        !field.is_empty() && field.parse::<f64>().is_err() && seen.insert(field.to_string())
+    })
+}
+
+/// Column indices and header names resolved from the CLI arguments.
+struct ColumnMapping {
+    body: usize,
+    headers: Vec<(Vec<u8>, usize)>,
+}
+
+impl ColumnMapping {
+    fn resolve(args: &CsvInputArgs, header_row: Option<&csv::ByteRecord>) -> Result<Self, String> {
+        let names: Option<Vec<&[u8]>> = header_row.map(|row| row.iter().collect());
+
+        let index_of = |col: &CsvColumn| -> Result<usize, String> {
+            match (col, &names) {
+                (CsvColumn::Index(idx), _) => Ok(*idx),
+                (CsvColumn::Name(name), Some(names)) => names
+                    .iter()
+                    .position(|n| *n == name.as_bytes())
+                    .ok_or_else(|| format!("Column '{name}' not found in header row")),
+                (CsvColumn::Name(name), None) => Err(format!(
+                    "Column '{name}' selected by name but input has no header row"
+                )),
+            }
+        };
+
+        let body = index_of(&args.csv_body_column)?;
+        let headers = args
+            .csv_header_column
+            .iter()
+            .map(|col| {
+                let idx = index_of(col)?;
+                let name = match &names {
+                    Some(names) => names.get(idx).map(|n| n.to_vec()),
+                    None => None,
+                }
+                .unwrap_or_else(|| idx.to_string().into_bytes());
+                Ok((name, idx))
+            })
+            .collect::<Result<_, String>>()?;
+
+        Ok(Self { body, headers })
+    }
+
+    fn to_record(&self, row: &csv::ByteRecord) -> Result<AppendRecord, String> {
+        let field = |idx: usize| {
+            row.get(idx)
+                .map(|f| f.to_vec())
+                .ok_or_else(|| format!("missing column {idx}"))
+        };
+
+        let headers = self
+            .headers
+            .iter()
+            .map(|(name, idx)| Ok(Header::new(name.clone(), field(*idx)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        AppendRecord::new(field(self.body)?)
+            .and_then(|record| record.with_headers(headers))
+            .map_err(|e| e.to_string())
+    }
+}
+
+/// Stream of append records parsed from CSV input.
+///
+/// Parsing happens on a dedicated thread since the CSV reader is blocking.
+/// The header row and column mapping are resolved before returning, so that
+/// mistakes in them are reported before anything is appended. Errors in later
+/// rows end the stream.
+pub async fn csv_records_stream(
+    input: &RecordsIn,
+    args: CsvInputArgs,
+    default_delimiter: u8,
+) -> Result<ReceiverStream<Result<AppendRecord, String>>, S2CliError> {
+    let builder = args
+        .reader_builder(default_delimiter)
+        .map_err(|e| S2CliError::InvalidArgs(miette::miette!("{e}")))?;
+
+    let reader: Box<dyn std::io::Read + Send> = match input {
+        RecordsIn::File(path) => Box::new(
+            std::fs::File::open(path).map_err(|e| S2CliError::RecordReaderInit(e.to_string()))?,
+        ),
+        RecordsIn::Stdin => Box::new(std::io::stdin()),
+    };
+
+    let (mapped_tx, mapped_rx) = oneshot::channel();
+    let (tx, rx) = mpsc::channel(s2::types::AppendRecordBatch::MAX_CAPACITY);
+    let _handle = std::thread::spawn(move || {
+        let mut reader = builder.from_reader(reader);
+        let mut rows = reader.byte_records();
+
+        let first_row = match rows.next() {
+            Some(Ok(row)) => row,
+            Some(Err(e)) => {
+                let _ = mapped_tx.send(Err(S2CliError::RecordRead(format!("CSV: {e}"))));
+                return;
+            }
+            None => {
+                let _ = mapped_tx.send(Ok(()));
+                return;
+            }
+        };
+
+        let has_header_row = match args.csv_header_row {
+            CsvHeaderRow::Present => true,
+            CsvHeaderRow::Absent => false,
+            CsvHeaderRow::Auto if args.selects_by_name() => true,
+            CsvHeaderRow::Auto => {
+                // Guessed from the row alone, so say which way it went.
+                let has_header_row = looks_like_header_row(&first_row);
+                let message = if has_header_row {
+                    "Treating the first CSV row as a header row, use '--csv-header-row absent' to append it"
+                } else {
+                    "Appending the first CSV row as data, use '--csv-header-row present' to skip it as a header row"
+                };
+                eprintln!("{}", message.yellow());
+                has_header_row
+            }
+        };
+
+        let mapping = match ColumnMapping::resolve(&args, has_header_row.then_some(&first_row)) {
+            Ok(mapping) => mapping,
+            Err(e) => {
+                let _ = mapped_tx.send(Err(S2CliError::InvalidArgs(miette::miette!(
+                    help = "Check --csv-body-column, --csv-header-column and --csv-header-row",
+                    "{e}"
+                ))));
+                return;
+            }
+        };
+        if mapped_tx.send(Ok(())).is_err() {
+            return;
+        }
+
+        let first_data_row = (!has_header_row).then_some(Ok(first_row));
+        for row in first_data_row.into_iter().chain(rows) {
+            let line = row
+                .as_ref()
+                .ok()
+                .and_then(|r| r.position())
+                .map_or(0, |p| p.line());
+            let record = row.map_err(|e| format!("CSV: {e}")).and_then(|row| {
+                mapping
+                    .to_record(&row)
+                    .map_err(|e| format!("CSV row at line {line}: {e}"))
+            });
+            let failed = record.is_err();
+            if tx.blocking_send(record).is_err() || failed {
+                return;
+            }
+        }
+    });
+
+    mapped_rx.await.expect("CSV reader thread")?;
+    Ok(ReceiverStream::new(rx))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(body: &str, headers: &[&str]) -> CsvInputArgs {
+        CsvInputArgs {
+            csv_body_column: body.parse().unwrap(),
+            csv_header_column: headers.iter().map(|h| h.parse().unwrap()).collect(),
+            csv_header_row: CsvHeaderRow::Auto,
+            csv_delimiter: None,
+            csv_quote: '"',
+            csv_escape: None,
+            csv_no_quoting: false,
+        }
+    }
+
+    #[test]
+    fn test_csv_column_mapping() {
+        let header_row = csv::ByteRecord::from(vec!["host", "level", "message"]);
+        let row = csv::ByteRecord::from(vec!["a.example", "error", "disk, full"]);
+
+        let mapping =
+            ColumnMapping::resolve(&args("message", &["host", "1"]), Some(&header_row)).unwrap();
+        let record = mapping.to_record(&row).unwrap();
+        assert_eq!(record.body(), b"disk, full");
+        assert_eq!(
+            record.headers(),
+            &[
+                Header::new("host", "a.example"),
+                Header::new("level", "error")
+            ]
+        );
+
+        let mapping = ColumnMapping::resolve(&args("2", &["0"]), None).unwrap();
+        let record = mapping.to_record(&row).unwrap();
+        assert_eq!(record.headers(), &[Header::new("0", "a.example")]);
+
+        assert!(ColumnMapping::resolve(&args("message", &[]), None).is_err());
+        assert!(ColumnMapping::resolve(&args("missing", &[]), Some(&header_row)).is_err());
+
+        let mapping = ColumnMapping::resolve(&args("5", &[]), None).unwrap();
+        assert!(mapping.to_record(&row).is_err());
+    }
+
+    #[test]
+    fn test_looks_like_header_row() {
+        assert!(looks_like_header_row(&csv::ByteRecord::from(vec![
+            "ts", "level", "message"
+        ])));
+        assert!(!looks_like_header_row(&csv::ByteRecord::from(vec![
+            "1700000000",
+            "error",
+            "oops"
+        ])));
+        assert!(!looks_like_header_row(&csv::ByteRecord::from(vec![
+            "a", "", "c"
+        ])));
+        assert!(!looks_like_header_row(&csv::ByteRecord::from(vec![
+            "a", "b", "a"
+        ])));
+    }
+}
@@ -1,8 +1,10 @@
+use std::path::Path;
+
 use miette::Diagnostic;
 use s2::{client::ClientError, types::ConvertError};
 use thiserror::Error;
 
-use crate::config::S2ConfigError;
+use crate::{config::S2ConfigError, schema::SchemaViolation};
 
 const HELP: &str = color_print::cstr!(
     "\n<cyan><bold>Notice something wrong?</bold></cyan>\n\n\
@@ -43,17 +45,72 @@ pub enum S2CliError {
     #[error("Failed to initialize a `Record Reader`! {0}")]
     RecordReaderInit(String),
 
+    #[error("Failed to load key file {path}: {reason}")]
+    #[diagnostic(help(
+        "Key files are TOML with a `key_id` and a hex encoded 32 byte `key`, \
+         which can be generated with `openssl rand -hex 32`."
+    ))]
+    KeyFile { path: String, reason: String },
+
+    #[error("Failed to encrypt record: {0}")]
+    #[diagnostic(help(
+        "Encryption adds 40 bytes to the body and a key ID header, which can \
+         push records close to 1 MiB over the limit."
+    ))]
+    RecordEncrypt(String),
+
+    #[error("Checkpoint {path}: {reason}")]
+    #[diagnostic(help(
+        "Use a separate checkpoint file for each consumer, or remove it to start over."
+    ))]
+    Checkpoint { path: String, reason: String },
+
+    #[error("Invalid JSON schema: {0}")]
+    #[diagnostic(transparent)]
+    InvalidSchema(miette::Report),
+
+    #[error(transparent)]
+    #[diagnostic(transparent)]
+    SchemaViolation(#[from] Box<SchemaViolation>),
+
     #[error("Stream mutated concurrently during ping")]
     PingStreamMutated,
 
+    #[error("Failed to read records: {0}")]
+    RecordRead(String),
+
     #[error("Failed to write records: {0}")]
     RecordWrite(String),
 
+    #[error("Exec command failed for {0}")]
+    Exec(String),
+
+    #[error("Terminal error: {0}")]
+    Terminal(std::io::Error),
+
+    #[error("Invalid bulk append manifest: {0}")]
+    #[diagnostic(transparent)]
+    InvalidManifest(miette::Report),
+
+    #[error("{failed} of {total} bulk appends failed, {skipped} skipped")]
+    BulkAppend {
+        failed: usize,
+        skipped: usize,
+        total: usize,
+    },
+
     #[error(transparent)]
     #[diagnostic(help("{}", HELP))]
     Service(#[from] ServiceError),
 }
 
+impl S2CliError {
+    /// A failure to write records to the file at `path`.
+    pub fn file_write(path: &Path, e: impl std::fmt::Display) -> Self {
+        Self::RecordWrite(format!("{}: {e}", path.display()))
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum ServiceErrorContext {
     ListBasins,
@@ -69,6 +126,7 @@ pub enum ServiceErrorContext {
     Trim,
     Fence,
     AppendSession,
+    Read,
     ReadSession,
     ReconfigureStream,
 }
@@ -89,6 +147,7 @@ impl std::fmt::Display for ServiceErrorContext {
             Self::Trim => write!(f, "Failed to trim"),
             Self::Fence => write!(f, "Failed to set fencing token"),
             Self::AppendSession => write!(f, "Failed to append session"),
+            Self::Read => write!(f, "Failed to read records"),
             Self::ReadSession => write!(f, "Failed to read session"),
             Self::ReconfigureStream => write!(f, "Failed to reconfigure stream"),
         }
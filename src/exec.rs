@@ -0,0 +1,237 @@
+//! Exec-per-batch consumer for read sessions.
+//!
+//! Records are piped to a command on stdin, one invocation per batch or per
+//! fixed number of records. A batch only counts as consumed once the command
+//! exits successfully, which gives at-least-once processing when combined with
+//! a checkpoint.
+
+use std::{process::Stdio, time::Duration};
+
+use clap::Args;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{backoff, error::S2CliError};
+
+/// Backoff before the first retry of a failed command, doubled on each
+/// further retry.
+const RETRY_BACKOFF_BASE: Duration = Duration::from_secs(1);
+const RETRY_BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+#[derive(Args, Debug, Clone)]
+#[command(next_help_heading = "Exec consumer")]
+pub struct ExecArgs {
+    /// Run this shell command for each batch of records, with the records on
+    /// stdin.
+    ///
+    /// The command gets the sequence numbers of the first and last record in
+    /// the S2_SEQ_START and S2_SEQ_END environment variables. With a
+    /// checkpoint, the position only advances past records once the command
+    /// has exited successfully for them.
+    #[arg(long, value_name = "COMMAND")]
+    pub exec: Option<String>,
+
+    /// Run the command for every this many records, instead of for each batch
+    /// read from the stream.
+    #[arg(
+        long,
+        value_name = "RECORDS",
+        requires = "exec",
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    pub exec_batch_size: Option<u64>,
+
+    /// Kill the command if it runs longer than this, which counts as a failure.
+    #[arg(long, value_name = "DURATION", requires = "exec")]
+    pub exec_timeout: Option<humantime::Duration>,
+
+    /// Retry a failed command this many times, with exponential backoff, before
+    /// giving up.
+    #[arg(long, value_name = "RETRIES", default_value_t = 3, requires = "exec")]
+    pub exec_retries: u32,
+}
+
+/// Writer that pipes records to a command.
+pub struct ExecWriter {
+    args: ExecArgs,
+    command: String,
+    buf: Vec<u8>,
+    records: u64,
+    first_seq_num: Option<u64>,
+    last_seq_num: u64,
+}
+
+impl ExecWriter {
+    /// Returns `None` if no command is configured.
+    pub fn new(args: &ExecArgs) -> Option<Self> {
+        Some(Self {
+            command: args.exec.clone()?,
+            args: args.clone(),
+            buf: Vec::new(),
+            records: 0,
+            first_seq_num: None,
+            last_seq_num: 0,
+        })
+    }
+
+    /// Sequence number of the first record not yet consumed by the command.
+    pub fn pending_seq_num(&self) -> Option<u64> {
+        self.first_seq_num
+    }
+
+    /// Buffer a batch of rendered records, given as the end offset in `buf` of
+    /// each record along with its sequence number, running the command
+    /// whenever enough records are buffered.
+    pub async fn write_batch(
+        &mut self,
+        buf: &[u8],
+        record_ends: &[(u64, usize)],
+    ) -> Result<(), S2CliError> {
+        let mut start = 0;
+        for &(seq_num, end) in record_ends {
+            self.buf.extend_from_slice(&buf[start..end]);
+            self.first_seq_num.get_or_insert(seq_num);
+            self.last_seq_num = seq_num;
+            self.records += 1;
+            start = end;
+
+            if self
+                .args
+                .exec_batch_size
+                .is_some_and(|size| self.records >= size)
+            {
+                self.run().await?;
+            }
+        }
+        if self.args.exec_batch_size.is_none() {
+            self.run().await?;
+        }
+        Ok(())
+    }
+
+    /// Run the command on the buffered records, if any, retrying on failure.
+    pub async fn run(&mut self) -> Result<(), S2CliError> {
+        let Some(first_seq_num) = self.first_seq_num else {
+            return Ok(());
+        };
+
+        let mut attempt = 0;
+        loop {
+            match self.run_once(first_seq_num).await {
+                Ok(()) => break,
+                Err(e) if attempt < self.args.exec_retries => {
+                    attempt += 1;
+                    let backoff = backoff(attempt, RETRY_BACKOFF_BASE, RETRY_BACKOFF_MAX);
+                    eprintln!(
+                        "Command failed for records {first_seq_num}..={}: {e}, \
+                         retrying in {} (attempt {attempt}/{})",
+                        self.last_seq_num,
+                        humantime::format_duration(backoff),
+                        self.args.exec_retries,
+                    );
+                    tokio::time::sleep(backoff).await;
+                }
+                Err(e) => {
+                    return Err(S2CliError::Exec(format!(
+                        "records {first_seq_num}..={}: {e}",
+                        self.last_seq_num
+                    )))
+                }
+            }
+        }
+
+        self.buf.clear();
+        self.records = 0;
+        self.first_seq_num = None;
+        Ok(())
+    }
+
+    async fn run_once(&self, first_seq_num: u64) -> Result<(), String> {
+        let mut child = shell_command(&self.command)
+            .env("S2_SEQ_START", first_seq_num.to_string())
+            .env("S2_SEQ_END", self.last_seq_num.to_string())
+            .stdin(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| format!("failed to spawn: {e}"))?;
+
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        let run = async {
+            // The command may exit without reading all of its input, which is
+            // up to it, so only its exit status decides success.
+            let _ = stdin.write_all(&self.buf).await;
+            drop(stdin);
+            child.wait().await
+        };
+
+        let status = match self.args.exec_timeout {
+            Some(timeout) => tokio::time::timeout(*timeout, run)
+                .await
+                .map_err(|_| format!("timed out after {timeout}"))?,
+            None => run.await,
+        }
+        .map_err(|e| e.to_string())?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(status.to_string())
+        }
+    }
+}
+
+#[cfg(unix)]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("sh");
+    cmd.arg("-c").arg(command);
+    cmd
+}
+
+#[cfg(windows)]
+fn shell_command(command: &str) -> Command {
+    let mut cmd = Command::new("cmd");
+    cmd.arg("/C").arg(command);
+    cmd
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    fn writer(command: &str, batch_size: Option<u64>) -> ExecWriter {
+        ExecWriter::new(&ExecArgs {
+            exec: Some(command.to_string()),
+            exec_batch_size: batch_size,
+            exec_timeout: None,
+            exec_retries: 0,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_exec_batches() {
+        let dir = TempDir::new("exec");
+        let command = format!(
+            "cat > {}/$S2_SEQ_START-$S2_SEQ_END.txt",
+            dir.path().display()
+        );
+
+        let mut exec = writer(&command, Some(2));
+        exec.write_batch(b"a\nb\nc\n", &[(3, 2), (4, 4), (5, 6)])
+            .await
+            .unwrap();
+        assert_eq!(exec.pending_seq_num(), Some(5));
+        exec.run().await.unwrap();
+        assert_eq!(exec.pending_seq_num(), None);
+
+        assert_eq!(std::fs::read(dir.join("3-4.txt")).unwrap(), b"a\nb\n");
+        assert_eq!(std::fs::read(dir.join("5-5.txt")).unwrap(), b"c\n");
+
+        let mut failing = writer("exit 3", None);
+        assert!(matches!(
+            failing.write_batch(b"a\n", &[(0, 2)]).await,
+            Err(S2CliError::Exec(_))
+        ));
+        assert_eq!(failing.pending_seq_num(), Some(0));
+    }
+}
@@ -0,0 +1,330 @@
+//! Client-side filtering of records in a read session.
+
+use std::str::FromStr;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use regex::bytes::Regex;
+use s2::types::SequencedRecord;
+
+/// Part of a record a predicate looks at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Field {
+    Header(Vec<u8>),
+    Body,
+    Len,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Eq(Vec<u8>),
+    Ne(Vec<u8>),
+    Matches(Regex),
+    NotMatches(Regex),
+    Gt(usize),
+    Ge(usize),
+    Lt(usize),
+    Le(usize),
+    LenEq(usize),
+    LenNe(usize),
+}
+
+/// A predicate on a record, like `header.level=error`, `body~timeout` or
+/// `len>1024`.
+///
+/// Headers and bodies can be compared with `=` and `!=`, or matched against a
+/// regular expression with `~` and `!~`. Body length can be compared with `=`,
+/// `!=`, `>`, `>=`, `<` and `<=`. Records without the named header only match
+/// `!=` and `!~` predicates on it.
+#[derive(Debug, Clone)]
+pub struct Predicate {
+    field: Field,
+    condition: Condition,
+}
+
+const OPERATORS: [&str; 8] = ["!=", "!~", ">=", "<=", "=", "~", ">", "<"];
+
+impl FromStr for Predicate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let op_idx = s
+            .find(['=', '!', '~', '<', '>'])
+            .ok_or_else(|| format!("missing operator in '{s}'"))?;
+        let (field, rest) = s.split_at(op_idx);
+        let op = OPERATORS
+            .iter()
+            .find(|op| rest.starts_with(**op))
+            .ok_or_else(|| format!("invalid operator in '{s}'"))?;
+        let value = &rest[op.len()..];
+
+        let field = match field {
+            "body" => Field::Body,
+            "len" => Field::Len,
+            _ => match field.strip_prefix("header.") {
+                Some(name) if !name.is_empty() => Field::Header(name.as_bytes().to_vec()),
+                _ => {
+                    return Err(format!(
+                        "invalid field '{field}', expected 'header.<name>', 'body' or 'len'"
+                    ))
+                }
+            },
+        };
+
+        let regex = || Regex::new(value).map_err(|e| format!("invalid regex '{value}': {e}"));
+        let length = || {
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("invalid length '{value}'"))
+        };
+
+        let condition = match (&field, *op) {
+            (Field::Len, "=") => Condition::LenEq(length()?),
+            (Field::Len, "!=") => Condition::LenNe(length()?),
+            (Field::Len, ">") => Condition::Gt(length()?),
+            (Field::Len, ">=") => Condition::Ge(length()?),
+            (Field::Len, "<") => Condition::Lt(length()?),
+            (Field::Len, "<=") => Condition::Le(length()?),
+            (Field::Len, _) => return Err(format!("'len' cannot be compared with '{op}'")),
+            (_, "=") => Condition::Eq(value.as_bytes().to_vec()),
+            (_, "!=") => Condition::Ne(value.as_bytes().to_vec()),
+            (_, "~") => Condition::Matches(regex()?),
+            (_, "!~") => Condition::NotMatches(regex()?),
+            (_, _) => return Err(format!("only 'len' can be compared with '{op}'")),
+        };
+
+        Ok(Self { field, condition })
+    }
+}
+
+impl Predicate {
+    fn matches(&self, record: &SequencedRecord) -> bool {
+        let value: Option<&[u8]> = match &self.field {
+            Field::Header(name) => record
+                .headers
+                .iter()
+                .find(|h| h.name == name[..])
+                .map(|h| h.value.as_ref()),
+            Field::Body => Some(&record.body),
+            Field::Len => {
+                let len = record.body.len();
+                return match self.condition {
+                    Condition::LenEq(n) => len == n,
+                    Condition::LenNe(n) => len != n,
+                    Condition::Gt(n) => len > n,
+                    Condition::Ge(n) => len >= n,
+                    Condition::Lt(n) => len < n,
+                    Condition::Le(n) => len <= n,
+                    _ => unreachable!("length condition"),
+                };
+            }
+        };
+
+        match (&self.condition, value) {
+            (Condition::Eq(expected), Some(value)) => value == &expected[..],
+            (Condition::Ne(expected), Some(value)) => value != &expected[..],
+            (Condition::Matches(regex), Some(value)) => regex.is_match(value),
+            (Condition::NotMatches(regex), Some(value)) => !regex.is_match(value),
+            (Condition::Ne(_) | Condition::NotMatches(_), None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// Deterministic sampling interval, given as `1/N` to keep every Nth record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleEvery(u64);
+
+impl FromStr for SampleEvery {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once('/') {
+            Some(("1", n)) => match n.trim().parse() {
+                Ok(n) if n > 0 => Ok(Self(n)),
+                _ => Err(format!(
+                    "invalid interval '{n}', expected a positive integer"
+                )),
+            },
+            _ => Err(format!("expected '1/N', got '{s}'")),
+        }
+    }
+}
+
+/// Fraction of records to keep at random, between 0 and 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SampleRate(f64);
+
+impl FromStr for SampleRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.parse() {
+            Ok(rate) if rate > 0.0 && rate <= 1.0 => Ok(Self(rate)),
+            _ => Err(format!("invalid rate '{s}', expected a number in (0, 1]")),
+        }
+    }
+}
+
+/// Picks which of the records matching a filter are kept.
+#[derive(Debug)]
+pub enum Sampler {
+    Every { interval: u64, seen: u64 },
+    Rate { rate: f64, rng: Box<StdRng> },
+}
+
+impl Sampler {
+    /// Keep every Nth record, starting with the first.
+    pub fn every(SampleEvery(interval): SampleEvery) -> Self {
+        Self::Every { interval, seen: 0 }
+    }
+
+    /// Keep each record with a probability of `rate`, picking the same
+    /// records on every run if seeded.
+    pub fn rate(SampleRate(rate): SampleRate, seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Self::Rate {
+            rate,
+            rng: Box::new(rng),
+        }
+    }
+
+    fn keep(&mut self) -> bool {
+        match self {
+            Self::Every { interval, seen } => {
+                let keep = *seen % *interval == 0;
+                *seen += 1;
+                keep
+            }
+            Self::Rate { rate, rng } => rng.gen_bool(*rate),
+        }
+    }
+}
+
+/// Conjunction of predicates, optionally sampled, with counts of the records
+/// it has seen.
+#[derive(Debug, Default)]
+pub struct RecordFilter {
+    predicates: Vec<Predicate>,
+    sampler: Option<Sampler>,
+    scanned: u64,
+    matched: u64,
+}
+
+impl RecordFilter {
+    pub fn new(predicates: Vec<Predicate>) -> Self {
+        Self {
+            predicates,
+            ..Default::default()
+        }
+    }
+
+    /// Only keep a sample of the records matching all predicates.
+    pub fn with_sampler(self, sampler: Option<Sampler>) -> Self {
+        Self { sampler, ..self }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.predicates.is_empty() && self.sampler.is_none()
+    }
+
+    /// Whether the record matches all predicates, and is picked by the
+    /// sampler if any.
+    pub fn matches(&mut self, record: &SequencedRecord) -> bool {
+        self.scanned += 1;
+        let matched = self.predicates.iter().all(|p| p.matches(record))
+            && self.sampler.as_mut().is_none_or(Sampler::keep);
+        if matched {
+            self.matched += 1;
+        }
+        matched
+    }
+
+    pub fn scanned(&self) -> u64 {
+        self.scanned
+    }
+
+    pub fn matched(&self) -> u64 {
+        self.matched
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use s2::types::Header;
+
+    use super::*;
+    use crate::test_util::record;
+
+    #[test]
+    fn test_predicates() {
+        let error = record(0, vec![Header::new("level", "error")], "disk full");
+        let plain = record(0, vec![], "request timeout after 30s");
+
+        let matches = |predicate: &str, record: &SequencedRecord| {
+            predicate.parse::<Predicate>().unwrap().matches(record)
+        };
+
+        assert!(matches("header.level=error", &error));
+        assert!(!matches("header.level=error", &plain));
+        assert!(matches("header.level!=info", &error));
+        assert!(matches("header.level!=info", &plain));
+        assert!(matches("header.level~^err", &error));
+        assert!(matches("body~time(out)?", &plain));
+        assert!(matches("body!~timeout", &error));
+        assert!(matches("body=disk full", &error));
+        assert!(matches("len>10", &plain));
+        assert!(matches("len<=9", &error));
+        assert!(!matches("len!=9", &error));
+
+        assert!("level=error".parse::<Predicate>().is_err());
+        assert!("header.=error".parse::<Predicate>().is_err());
+        assert!("len~1".parse::<Predicate>().is_err());
+        assert!("len>x".parse::<Predicate>().is_err());
+        assert!("body>1".parse::<Predicate>().is_err());
+        assert!("body~(".parse::<Predicate>().is_err());
+        assert!("body".parse::<Predicate>().is_err());
+    }
+
+    #[test]
+    fn test_filter_counts() {
+        let mut filter = RecordFilter::new(vec![
+            "header.level=error".parse().unwrap(),
+            "len>4".parse().unwrap(),
+        ]);
+        assert!(filter.matches(&record(0, vec![Header::new("level", "error")], "disk full")));
+        assert!(!filter.matches(&record(0, vec![Header::new("level", "error")], "oops")));
+        assert!(!filter.matches(&record(0, vec![], "disk full")));
+        assert_eq!((filter.matched(), filter.scanned()), (1, 3));
+    }
+
+    #[test]
+    fn test_sampling() {
+        assert_eq!("1/10".parse(), Ok(SampleEvery(10)));
+        assert!("1/0".parse::<SampleEvery>().is_err());
+        assert!("2/10".parse::<SampleEvery>().is_err());
+        assert!("0".parse::<SampleRate>().is_err());
+        assert!("1.5".parse::<SampleRate>().is_err());
+
+        let mut filter = RecordFilter::new(vec!["len>1".parse().unwrap()])
+            .with_sampler(Some(Sampler::every(SampleEvery(2))));
+        let kept: Vec<_> = ["a", "bb", "cc", "dd", "ee", "f"]
+            .into_iter()
+            .map(|body| filter.matches(&record(0, vec![], body)))
+            .collect();
+        assert_eq!(kept, [false, true, false, true, false, false]);
+        assert_eq!((filter.matched(), filter.scanned()), (2, 6));
+
+        let sample = |seed| {
+            let mut filter = RecordFilter::new(vec![])
+                .with_sampler(Some(Sampler::rate(SampleRate(0.5), Some(seed))));
+            (0..64)
+                .map(|_| filter.matches(&record(0, vec![], "")))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(sample(7), sample(7));
+        assert!(sample(7).contains(&true) && sample(7).contains(&false));
+    }
+}
@@ -0,0 +1,747 @@
+//! Output formats for records in a read session.
+
+use std::{borrow::Cow, str::FromStr};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use clap::ValueEnum;
+use s2::types::{CommandRecord, SequencedRecord};
+use serde::Serialize;
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Record bodies only, one per line.
+    #[default]
+    Text,
+    /// JSON Lines, with one object per record holding its sequence number,
+    /// headers and body. Command records are included with a `command` field.
+    Json,
+}
+
+/// Encoding of record bodies and headers in JSON output.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BodyEncoding {
+    /// UTF-8 if the body and headers are valid UTF-8, base64 otherwise.
+    #[default]
+    Auto,
+    /// UTF-8, replacing invalid sequences.
+    Utf8,
+    /// Base64 (standard alphabet, with padding).
+    Base64,
+}
+
+/// How record bodies are shown in text output.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BodyDisplay {
+    /// Escaped if the body isn't valid UTF-8 and output is a terminal, raw
+    /// otherwise.
+    #[default]
+    Auto,
+    /// Hexdump with offsets and printable characters, like `hexdump -C`.
+    /// Template fields are escaped instead, as a hexdump takes several lines.
+    Hex,
+    /// Control characters and invalid UTF-8 escaped, like `\x1b`.
+    Escape,
+    /// Bytes as they are.
+    Raw,
+}
+
+impl BodyDisplay {
+    /// Append `bytes` shown this way to `buf`.
+    fn write(self, bytes: &[u8], buf: &mut Vec<u8>) {
+        match self {
+            Self::Raw => buf.extend_from_slice(bytes),
+            Self::Auto if std::str::from_utf8(bytes).is_ok() => buf.extend_from_slice(bytes),
+            Self::Auto | Self::Escape => write_escaped(bytes, buf),
+            Self::Hex => write_hexdump(bytes, buf),
+        }
+    }
+
+    /// Append `bytes` shown this way within a line to `buf`.
+    fn write_inline(self, bytes: &[u8], buf: &mut Vec<u8>) {
+        match self {
+            Self::Hex => write_escaped(bytes, buf),
+            display => display.write(bytes, buf),
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Encoding {
+    Utf8,
+    Base64,
+}
+
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    seq_num: u64,
+    headers: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+    body: Cow<'a, str>,
+    encoding: Encoding,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum JsonCommand {
+    Fence { fencing_token: String },
+    Trim { trim_point: u64 },
+}
+
+#[derive(Serialize)]
+struct JsonCommandRecord {
+    seq_num: u64,
+    command: JsonCommand,
+}
+
+fn encode(bytes: &[u8], utf8: bool) -> Cow<'_, str> {
+    if utf8 {
+        String::from_utf8_lossy(bytes)
+    } else {
+        Cow::Owned(BASE64.encode(bytes))
+    }
+}
+
+/// Write `bytes` on a single line, escaping control characters and invalid
+/// UTF-8.
+pub fn write_escaped(bytes: &[u8], buf: &mut Vec<u8>) {
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\\' => buf.extend_from_slice(b"\\\\"),
+                '\t' => buf.extend_from_slice(b"\\t"),
+                '\n' => buf.extend_from_slice(b"\\n"),
+                '\r' => buf.extend_from_slice(b"\\r"),
+                c if c.is_ascii_control() => {
+                    buf.extend_from_slice(format!("\\x{:02x}", c as u8).as_bytes())
+                }
+                c if c.is_control() => {
+                    buf.extend_from_slice(format!("\\u{{{:x}}}", c as u32).as_bytes())
+                }
+                c => buf.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+        for b in chunk.invalid() {
+            buf.extend_from_slice(format!("\\x{b:02x}").as_bytes());
+        }
+    }
+}
+
+/// Write `bytes` as lines of 16 bytes each, with the offset, hex values and
+/// printable ASCII characters.
+pub fn write_hexdump(bytes: &[u8], buf: &mut Vec<u8>) {
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        let mut hex = String::with_capacity(49);
+        for (i, b) in chunk.iter().enumerate() {
+            if i == 8 {
+                hex.push(' ');
+            }
+            hex.push_str(&format!("{b:02x} "));
+        }
+        let ascii: String = chunk
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        buf.extend_from_slice(format!("{:08x}  {hex:<49} |{ascii}|\n", line * 16).as_bytes());
+    }
+}
+
+/// A piece of a parsed output template.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TemplatePart {
+    Literal(Vec<u8>),
+    SeqNum,
+    Header(Vec<u8>),
+    Body,
+    BodyLen,
+    BodyHex,
+    BodyBase64,
+}
+
+/// Output template with placeholders in braces, like `{seq_num}\t{body}`.
+///
+/// Supported placeholders are `seq_num`, `header.<name>`, `body`, `body_len`,
+/// `body_hex` and `body_base64`. Use `{{` and `}}` for literal braces, and
+/// `\t`, `\n` or `\\` for tabs, newlines and backslashes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Template {
+    parts: Vec<TemplatePart>,
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = Vec::new();
+        let mut literal = Vec::new();
+        let mut chars = s.char_indices().peekable();
+
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '{' if chars.next_if(|(_, c)| *c == '{').is_some() => literal.push(b'{'),
+                '}' if chars.next_if(|(_, c)| *c == '}').is_some() => literal.push(b'}'),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some((_, '}')) => break,
+                            Some((_, c)) => name.push(c),
+                            None => return Err(format!("unclosed '{{' at position {idx}")),
+                        }
+                    }
+                    let part = match name.as_str() {
+                        "seq_num" => TemplatePart::SeqNum,
+                        "body" => TemplatePart::Body,
+                        "body_len" => TemplatePart::BodyLen,
+                        "body_hex" => TemplatePart::BodyHex,
+                        "body_base64" => TemplatePart::BodyBase64,
+                        _ => match name.strip_prefix("header.") {
+                            Some(header) if !header.is_empty() => {
+                                TemplatePart::Header(header.as_bytes().to_vec())
+                            }
+                            _ => {
+                                return Err(format!(
+                                    "unknown placeholder '{{{name}}}' at position {idx}, \
+                                     expected one of {{seq_num}}, {{header.<name>}}, {{body}}, \
+                                     {{body_len}}, {{body_hex}} or {{body_base64}}"
+                                ))
+                            }
+                        },
+                    };
+                    if !literal.is_empty() {
+                        parts.push(TemplatePart::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(part);
+                }
+                '}' => return Err(format!("unmatched '}}' at position {idx}")),
+                '\\' => match chars.next() {
+                    Some((_, 't')) => literal.push(b'\t'),
+                    Some((_, 'n')) => literal.push(b'\n'),
+                    Some((_, 'r')) => literal.push(b'\r'),
+                    Some((_, '\\')) => literal.push(b'\\'),
+                    Some((_, c)) => {
+                        literal.push(b'\\');
+                        literal.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    }
+                    None => literal.push(b'\\'),
+                },
+                c => literal.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+            }
+        }
+
+        if !literal.is_empty() {
+            parts.push(TemplatePart::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+}
+
+impl Template {
+    fn write_record(&self, record: &SequencedRecord, display: BodyDisplay, buf: &mut Vec<u8>) {
+        for part in &self.parts {
+            match part {
+                TemplatePart::Literal(literal) => buf.extend_from_slice(literal),
+                TemplatePart::SeqNum => {
+                    buf.extend_from_slice(record.seq_num.to_string().as_bytes())
+                }
+                TemplatePart::Header(name) => {
+                    if let Some(header) = record.headers.iter().find(|h| h.name == name[..]) {
+                        display.write_inline(&header.value, buf);
+                    }
+                }
+                TemplatePart::Body => display.write_inline(&record.body, buf),
+                TemplatePart::BodyLen => {
+                    buf.extend_from_slice(record.body.len().to_string().as_bytes())
+                }
+                TemplatePart::BodyHex => {
+                    buf.extend_from_slice(base16ct::lower::encode_string(&record.body).as_bytes())
+                }
+                TemplatePart::BodyBase64 => {
+                    buf.extend_from_slice(BASE64.encode(&record.body).as_bytes())
+                }
+            }
+        }
+    }
+}
+
+/// How command records are handled in a read session.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandsMode {
+    /// Leave command records out.
+    Skip,
+    /// Describe command records on stderr.
+    Stderr,
+    /// Write command records into the output, as JSON objects with a `command`
+    /// field for JSON output, or as lines starting with `[s2:fence]` or
+    /// `[s2:trim]` otherwise.
+    Inline,
+}
+
+/// Format of fields selected from JSON record bodies.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SelectFormat {
+    /// A JSON array of the selected values, with null for missing fields.
+    #[default]
+    Json,
+    /// Tab-separated values, with strings unquoted and missing fields empty.
+    Tsv,
+}
+
+/// What to do with records whose body is not JSON when selecting fields.
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NonJsonRecords {
+    /// Leave the record out of the output.
+    #[default]
+    Skip,
+    /// Output the record body as is.
+    Pass,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/// Comma-separated paths of fields to select from JSON record bodies, like
+/// `.user.id, .tags[0]`. A single `.` selects the whole body.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldSelection {
+    paths: Vec<Vec<PathSegment>>,
+}
+
+fn parse_path(path: &str) -> Result<Vec<PathSegment>, String> {
+    let invalid = || format!("invalid field path '{path}'");
+    if path == "." {
+        return Ok(Vec::new());
+    }
+    if !path.starts_with('.') {
+        return Err(format!("field path '{path}' must start with '.'"));
+    }
+
+    let mut segments = Vec::new();
+    let mut rest = path;
+    while !rest.is_empty() {
+        if let Some(after_dot) = rest.strip_prefix('.') {
+            let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+            let key = &after_dot[..end];
+            if key.is_empty() {
+                return Err(invalid());
+            }
+            segments.push(PathSegment::Key(key.to_string()));
+            rest = &after_dot[end..];
+        } else if let Some(after_bracket) = rest.strip_prefix('[') {
+            let (index, after_index) = after_bracket.split_once(']').ok_or_else(invalid)?;
+            let index = index.trim().parse().map_err(|_| invalid())?;
+            segments.push(PathSegment::Index(index));
+            rest = after_index;
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(segments)
+}
+
+impl FromStr for FieldSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let paths = s
+            .split(',')
+            .map(|path| parse_path(path.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { paths })
+    }
+}
+
+impl FieldSelection {
+    fn select<'a>(&self, value: &'a serde_json::Value) -> Vec<Option<&'a serde_json::Value>> {
+        self.paths
+            .iter()
+            .map(|path| {
+                path.iter().try_fold(value, |value, segment| match segment {
+                    PathSegment::Key(key) => value.get(key),
+                    PathSegment::Index(idx) => value.get(idx),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Projection of JSON record bodies onto selected fields.
+#[derive(Debug, Clone)]
+pub struct Projection {
+    pub fields: FieldSelection,
+    pub format: SelectFormat,
+    pub non_json: NonJsonRecords,
+}
+
+impl Projection {
+    fn write_record(&self, record: &SequencedRecord, display: BodyDisplay, buf: &mut Vec<u8>) {
+        let Ok(value) = serde_json::from_slice::<serde_json::Value>(&record.body) else {
+            if self.non_json == NonJsonRecords::Pass {
+                display.write(&record.body, buf);
+                buf.push(b'\n');
+            }
+            return;
+        };
+
+        let selected = self.fields.select(&value);
+        match self.format {
+            SelectFormat::Json => {
+                serde_json::to_writer(&mut *buf, &selected).expect("serializable values");
+            }
+            SelectFormat::Tsv => {
+                for (idx, value) in selected.into_iter().enumerate() {
+                    if idx > 0 {
+                        buf.push(b'\t');
+                    }
+                    let field = match value {
+                        None | Some(serde_json::Value::Null) => continue,
+                        Some(serde_json::Value::String(s)) => Cow::Borrowed(s.as_str()),
+                        Some(value) => Cow::Owned(value.to_string()),
+                    };
+                    for c in field.chars() {
+                        match c {
+                            '\t' => buf.extend_from_slice(b"\\t"),
+                            '\n' => buf.extend_from_slice(b"\\n"),
+                            '\r' => buf.extend_from_slice(b"\\r"),
+                            '\\' => buf.extend_from_slice(b"\\\\"),
+                            c => buf.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                        }
+                    }
+                }
+            }
+        }
+        buf.push(b'\n');
+    }
+}
+
+/// Renders records in a read session into an output buffer.
+#[derive(Debug, Clone, Default)]
+pub struct RecordFormatter {
+    format: OutputFormat,
+    encoding: BodyEncoding,
+    display: BodyDisplay,
+    template: Option<Template>,
+    projection: Option<Projection>,
+}
+
+impl RecordFormatter {
+    pub fn new(format: OutputFormat, encoding: BodyEncoding) -> Self {
+        Self {
+            format,
+            encoding,
+            display: BodyDisplay::Auto,
+            template: None,
+            projection: None,
+        }
+    }
+
+    /// A formatter for another output format, with the same body encoding
+    /// and display.
+    pub fn for_format(&self, format: OutputFormat) -> Self {
+        Self {
+            display: self.display,
+            ..Self::new(format, self.encoding)
+        }
+    }
+
+    /// Show bodies in text output this way.
+    pub fn with_display(self, display: BodyDisplay) -> Self {
+        Self { display, ..self }
+    }
+
+    /// A formatter for an output that is a terminal or not, which only
+    /// escapes bodies automatically on terminals.
+    pub fn for_terminal(&self, terminal: bool) -> Self {
+        let mut formatter = self.clone();
+        if formatter.display == BodyDisplay::Auto && !terminal {
+            formatter.display = BodyDisplay::Raw;
+        }
+        formatter
+    }
+
+    /// Render records with the template instead of the output format.
+    pub fn with_template(self, template: Option<Template>) -> Self {
+        Self { template, ..self }
+    }
+
+    /// Render selected fields of JSON bodies instead of the output format.
+    pub fn with_projection(self, projection: Option<Projection>) -> Self {
+        Self { projection, ..self }
+    }
+
+    /// How command records are handled unless specified: inline for JSON
+    /// output, and on stderr otherwise.
+    pub fn default_commands_mode(&self) -> CommandsMode {
+        if self.template.is_none() && self.projection.is_none() && self.format == OutputFormat::Json
+        {
+            CommandsMode::Inline
+        } else {
+            CommandsMode::Stderr
+        }
+    }
+
+    /// Whether records and command records are rendered as JSON objects.
+    pub fn writes_json(&self) -> bool {
+        match (&self.template, &self.projection) {
+            (Some(_), _) => false,
+            (None, Some(projection)) => projection.format == SelectFormat::Json,
+            (None, None) => self.format == OutputFormat::Json,
+        }
+    }
+
+    /// Append the rendered record, including the trailing newline, to `buf`.
+    pub fn write_record(&self, record: &SequencedRecord, buf: &mut Vec<u8>) {
+        if let Some(template) = &self.template {
+            template.write_record(record, self.display, buf);
+            buf.push(b'\n');
+            return;
+        }
+        if let Some(projection) = &self.projection {
+            projection.write_record(record, self.display, buf);
+            return;
+        }
+
+        match self.format {
+            OutputFormat::Text => self.display.write(&record.body, buf),
+            OutputFormat::Json => {
+                let utf8 = match self.encoding {
+                    BodyEncoding::Auto => {
+                        std::str::from_utf8(&record.body).is_ok()
+                            && record.headers.iter().all(|h| {
+                                std::str::from_utf8(&h.name).is_ok()
+                                    && std::str::from_utf8(&h.value).is_ok()
+                            })
+                    }
+                    BodyEncoding::Utf8 => true,
+                    BodyEncoding::Base64 => false,
+                };
+                let json_record = JsonRecord {
+                    seq_num: record.seq_num,
+                    headers: record
+                        .headers
+                        .iter()
+                        .map(|h| (encode(&h.name, utf8), encode(&h.value, utf8)))
+                        .collect(),
+                    body: encode(&record.body, utf8),
+                    encoding: if utf8 {
+                        Encoding::Utf8
+                    } else {
+                        Encoding::Base64
+                    },
+                };
+                serde_json::to_writer(&mut *buf, &json_record).expect("serializable record");
+            }
+        }
+        buf.push(b'\n');
+    }
+
+    /// Append the rendered command record, including the trailing newline,
+    /// to `buf`.
+    pub fn write_command(&self, seq_num: u64, command: &CommandRecord, buf: &mut Vec<u8>) {
+        let command = match command {
+            CommandRecord::Fence { fencing_token } => JsonCommand::Fence {
+                fencing_token: base16ct::lower::encode_string(fencing_token.as_ref()),
+            },
+            CommandRecord::Trim { seq_num } => JsonCommand::Trim {
+                trim_point: *seq_num,
+            },
+        };
+        if self.writes_json() {
+            serde_json::to_writer(&mut *buf, &JsonCommandRecord { seq_num, command })
+                .expect("serializable command record");
+        } else {
+            let line = match command {
+                JsonCommand::Fence { fencing_token } => {
+                    format!("[s2:fence] seq_num={seq_num} fencing_token={fencing_token}")
+                }
+                JsonCommand::Trim { trim_point } => {
+                    format!("[s2:trim] seq_num={seq_num} trim_point={trim_point}")
+                }
+            };
+            buf.extend_from_slice(line.as_bytes());
+        }
+        buf.push(b'\n');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use s2::types::{FencingToken, Header};
+
+    use super::*;
+    use crate::test_util::record;
+
+    fn render(formatter: &RecordFormatter, record: &SequencedRecord) -> String {
+        let mut buf = Vec::new();
+        formatter.write_record(record, &mut buf);
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_json_format() {
+        let json = RecordFormatter::new(OutputFormat::Json, BodyEncoding::Auto);
+        let utf8 = record(7, vec![Header::new("host", "a")], b"hello");
+        assert_eq!(
+            render(&json, &utf8),
+            "{\"seq_num\":7,\"headers\":[[\"host\",\"a\"]],\"body\":\"hello\",\"encoding\":\"utf8\"}\n"
+        );
+
+        let binary = record(7, vec![], b"\xff\x00");
+        assert_eq!(
+            render(&json, &binary),
+            "{\"seq_num\":7,\"headers\":[],\"body\":\"/wA=\",\"encoding\":\"base64\"}\n"
+        );
+
+        let base64 = RecordFormatter::new(OutputFormat::Json, BodyEncoding::Base64);
+        assert_eq!(
+            render(&base64, &utf8),
+            "{\"seq_num\":7,\"headers\":[[\"aG9zdA==\",\"YQ==\"]],\"body\":\"aGVsbG8=\",\"encoding\":\"base64\"}\n"
+        );
+
+        let mut buf = Vec::new();
+        json.write_command(
+            3,
+            &CommandRecord::fence(FencingToken::new(vec![0xab]).unwrap()),
+            &mut buf,
+        );
+        json.write_command(4, &CommandRecord::trim(2u64), &mut buf);
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "{\"seq_num\":3,\"command\":{\"type\":\"fence\",\"fencing_token\":\"ab\"}}\n\
+             {\"seq_num\":4,\"command\":{\"type\":\"trim\",\"trim_point\":2}}\n"
+        );
+    }
+
+    #[test]
+    fn test_text_format() {
+        let text = RecordFormatter::default();
+        assert_eq!(render(&text, &record(7, vec![], b"hello")), "hello\n");
+
+        let mut buf = Vec::new();
+        text.write_command(
+            3,
+            &CommandRecord::fence(FencingToken::new(vec![0xab]).unwrap()),
+            &mut buf,
+        );
+        text.write_command(4, &CommandRecord::trim(2u64), &mut buf);
+        assert_eq!(
+            String::from_utf8(buf).unwrap(),
+            "[s2:fence] seq_num=3 fencing_token=ab\n[s2:trim] seq_num=4 trim_point=2\n"
+        );
+    }
+
+    #[test]
+    fn test_body_display() {
+        let binary = record(7, vec![], b"ok\t\x1b[0m\xff\n");
+        let auto = RecordFormatter::default();
+        assert_eq!(render(&auto, &binary), "ok\\t\\x1b[0m\\xff\\n\n");
+        let mut buf = Vec::new();
+        auto.for_terminal(false).write_record(&binary, &mut buf);
+        assert_eq!(buf, b"ok\t\x1b[0m\xff\n\n");
+        assert_eq!(render(&auto, &record(7, vec![], b"caf\xc3\xa9")), "café\n");
+
+        let hex = auto.with_display(BodyDisplay::Hex);
+        assert_eq!(
+            render(
+                &hex,
+                &record(7, vec![], b"hello, world\n\x00\x01\x02\x03\xff")
+            ),
+            "00000000  68 65 6c 6c 6f 2c 20 77  6f 72 6c 64 0a 00 01 02  |hello, world....|\n\
+             00000010  03 ff                                             |..|\n\n"
+        );
+
+        let binary = record(7, vec![Header::new("host", b"a\x1b".as_slice())], b"\xff");
+        let template = hex.with_template(Some("{header.host} {body}".parse().unwrap()));
+        assert_eq!(render(&template, &binary), "a\\x1b \\xff\n");
+        let pass = RecordFormatter::default().with_projection(Some(Projection {
+            fields: ".a".parse().unwrap(),
+            format: SelectFormat::Json,
+            non_json: NonJsonRecords::Pass,
+        }));
+        assert_eq!(render(&pass, &binary), "\\xff\n");
+        let mut buf = Vec::new();
+        pass.for_terminal(false).write_record(&binary, &mut buf);
+        assert_eq!(buf, b"\xff\n");
+    }
+
+    #[test]
+    fn test_template_format() {
+        let record = record(
+            7,
+            vec![
+                Header::new("host", "a.example"),
+                Header::new("level", "info"),
+            ],
+            b"hi",
+        );
+        let render_template = |template: &str| {
+            let formatter =
+                RecordFormatter::default().with_template(Some(template.parse().unwrap()));
+            render(&formatter, &record)
+        };
+
+        assert_eq!(
+            render_template(r"{seq_num}\t{header.host}\t{body}"),
+            "7\ta.example\thi\n"
+        );
+        assert_eq!(
+            render_template("{{{body_len}}} {body_hex} {body_base64} {header.missing}|"),
+            "{2} 6869 aGk= |\n"
+        );
+
+        assert!("{seq}".parse::<Template>().is_err());
+        assert!("{header.}".parse::<Template>().is_err());
+        assert!("{body".parse::<Template>().is_err());
+        assert!("body}".parse::<Template>().is_err());
+    }
+
+    #[test]
+    fn test_projection() {
+        let projection = |fields: &str, format, non_json| {
+            RecordFormatter::default().with_projection(Some(Projection {
+                fields: fields.parse().unwrap(),
+                format,
+                non_json,
+            }))
+        };
+        let event = record(
+            7,
+            vec![],
+            br#"{"user": {"id": 42}, "event": "log\tin", "tags": ["a", "b"]}"#,
+        );
+        let text = record(7, vec![], b"not json");
+
+        let json = projection(
+            ".user.id, .event, .tags[1], .missing",
+            SelectFormat::Json,
+            NonJsonRecords::Skip,
+        );
+        assert_eq!(render(&json, &event), "[42,\"log\\tin\",\"b\",null]\n");
+        assert_eq!(render(&json, &text), "");
+
+        let tsv = projection(
+            ".event,.missing,.user",
+            SelectFormat::Tsv,
+            NonJsonRecords::Pass,
+        );
+        assert_eq!(render(&tsv, &event), "log\\tin\t\t{\"id\":42}\n");
+        assert_eq!(render(&tsv, &text), "not json\n");
+
+        assert_eq!(
+            ".".parse::<FieldSelection>().unwrap().paths,
+            vec![Vec::new()]
+        );
+        assert!("user.id".parse::<FieldSelection>().is_err());
+        assert!(".a..b".parse::<FieldSelection>().is_err());
+        assert!(".a[x]".parse::<FieldSelection>().is_err());
+        assert!(".a,".parse::<FieldSelection>().is_err());
+    }
+}
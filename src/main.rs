@@ -1,5 +1,6 @@
 use std::{
-    io::BufRead,
+    collections::HashMap,
+    io::{IsTerminal, Read},
     path::PathBuf,
     pin::Pin,
     time::{Duration, UNIX_EPOCH},
@@ -7,33 +8,48 @@ use std::{
 
 use account::AccountService;
 use basin::BasinService;
-use clap::{builder::styling, Parser, Subcommand};
+use bytes::{Bytes, BytesMut};
+use checkpoint::Checkpoint;
+use clap::{builder::styling, Parser, Subcommand, ValueEnum};
 use colored::*;
+use columnar::ColumnarArgs;
 use config::{config_path, create_config};
+use crypto::{RecordKey, RecordKeyring};
+use csv_input::{csv_records_stream, CsvInputArgs};
 use error::{S2CliError, ServiceError, ServiceErrorContext};
+use exec::ExecArgs;
+use filter::{Predicate, RecordFilter, SampleEvery, SampleRate, Sampler};
+use formats::{
+    BodyDisplay, BodyEncoding, CommandsMode, FieldSelection, NonJsonRecords, OutputFormat,
+    Projection, RecordFormatter, SelectFormat, Template,
+};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use ping::{LatencyStats, PingResult, Pinger};
 use rand::Rng;
+use read::{ReadBounds, ReadOutputs, ReadSession};
+use rotate::RotateArgs;
 use s2::{
     batching::AppendRecordsBatchingOpts,
     client::{BasinClient, Client, ClientConfig, S2Endpoints, StreamClient},
-    types::{
-        AppendRecordBatch, BasinInfo, CommandRecord, ConvertError, FencingToken, MeteredBytes as _,
-        ReadOutput, StreamInfo,
-    },
+    types::{AppendRecord, BasinInfo, CommandRecord, ConvertError, FencingToken, StreamInfo},
 };
-use stream::{RecordStream, StreamService};
+use schema::{validate_records, OnInvalid, SchemaValidator};
+use sink::{SinkSpec, Sinks, TableSinkSpec};
+use stats::{ReadStats, StatsFormat};
+use stream::{until_error, NewlineDelimitedCodec, RecordStream, StreamService};
+use tail::{DiscoveredStreams, StreamDiscovery, TailStart, Tailer};
 use tokio::{
     fs::{File, OpenOptions},
-    io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufWriter},
+    io::{AsyncRead, AsyncWrite, BufWriter},
     select,
-    time::Instant,
+    time::{Instant, Interval},
 };
-use tokio::{signal, sync::mpsc};
-use tokio_stream::{
-    wrappers::{LinesStream, ReceiverStream},
-    Stream, StreamExt,
+use tokio::{
+    signal,
+    sync::{mpsc, oneshot},
 };
+use tokio_stream::{wrappers::ReceiverStream, Stream, StreamExt};
+use tokio_util::{codec::FramedRead, io::StreamReader};
 use tracing::trace;
 use tracing_subscriber::{fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt};
 use types::{
@@ -43,11 +59,29 @@ use types::{
 
 mod account;
 mod basin;
+mod browse;
+mod bulk;
+mod checkpoint;
+mod columnar;
 mod stream;
 
 mod config;
+mod crypto;
+mod csv_input;
 mod error;
+mod exec;
+mod filter;
+mod formats;
 mod ping;
+mod read;
+mod rotate;
+mod schema;
+mod sink;
+mod sqlite;
+mod stats;
+mod tail;
+#[cfg(test)]
+mod test_util;
 mod types;
 
 const STYLES: styling::Styles = styling::Styles::styled()
@@ -63,6 +97,29 @@ const GENERAL_USAGE: &str = color_print::cstr!(
     "#
 );
 
+/// Backoff before the first attempt to reconnect a read session, doubled on
+/// each further attempt up to the maximum.
+const RECONNECT_BACKOFF_BASE: Duration = Duration::from_millis(500);
+const RECONNECT_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Backoff before retry `attempt`, counting from 1: `base` doubled for each
+/// earlier attempt, up to `max`.
+fn backoff(attempt: u32, base: Duration, max: Duration) -> Duration {
+    base.saturating_mul(1 << attempt.saturating_sub(1).min(16))
+        .min(max)
+}
+
+/// Wait for the next tick of an optional interval, for use in `select!`.
+/// Never completes if there is no interval.
+async fn tick(interval: &mut Option<Interval>) {
+    match interval {
+        Some(interval) => {
+            interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
 #[derive(Parser, Debug)]
 #[command(version, override_usage = GENERAL_USAGE, styles = STYLES)]
 struct Cli {
@@ -70,6 +127,8 @@ struct Cli {
     command: Commands,
 }
 
+// Parsed once per invocation, so the size of the read variant doesn't matter.
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand, Debug)]
 enum Commands {
     /// Manage CLI configuration.
@@ -232,7 +291,8 @@ enum Commands {
 
     /// Append records to a stream.
     ///
-    /// Currently, only newline delimited records are supported.
+    /// Records are either newline delimited, or rows of CSV/TSV input with
+    /// columns mapped to the record body and headers.
     Append {
         #[command(flatten)]
         args: BasinNameAndStreamArgs,
@@ -245,11 +305,53 @@ enum Commands {
         #[arg(short = 'm', long)]
         match_seq_num: Option<u64>,
 
-        /// Input newline delimited records to append from a file or stdin.
-        /// All records are treated as plain text.
+        /// Input records to append from a file or stdin.
         /// Use "-" to read from stdin.
         #[arg(short = 'i', long, value_parser = parse_records_input_source, default_value = "-")]
         input: RecordsIn,
+
+        /// Format of the input records.
+        #[arg(long, value_enum, default_value_t = InputFormat::Text)]
+        format: InputFormat,
+
+        #[command(flatten)]
+        csv: CsvInputArgs,
+
+        /// Encrypt record bodies with the key in this file.
+        ///
+        /// The key file is TOML with a `key_id` and a hex encoded 32 byte `key`.
+        /// Bodies are sealed with XChaCha20-Poly1305, and the key id is added
+        /// as the `s2-key-id` record header.
+        #[arg(long, value_name = "KEYFILE")]
+        encrypt_key: Option<PathBuf>,
+
+        /// Validate record bodies as JSON against this JSON Schema before
+        /// they are appended.
+        #[arg(long, value_name = "SCHEMA")]
+        schema: Option<PathBuf>,
+
+        /// What to do with records that do not match the schema.
+        #[arg(long, value_enum, default_value_t = OnInvalid::Reject, requires = "schema")]
+        on_invalid: OnInvalid,
+
+        /// Write records that do not match the schema to this file, one per line.
+        #[arg(long, value_name = "FILE", requires = "schema")]
+        dead_letter: Option<PathBuf>,
+    },
+
+    /// Append records from many files to many streams.
+    ///
+    /// The manifest lists input files along with the S2 URIs of the streams
+    /// they should be appended to. It can be a TOML file with `[[append]]`
+    /// tables holding `input` and `uri` keys, or a CSV file with `input,uri`
+    /// rows. Relative input paths are resolved against the manifest directory.
+    BulkAppend {
+        /// Path to the manifest (`.toml` or `.csv`).
+        manifest: PathBuf,
+
+        /// Maximum number of append sessions to run concurrently.
+        #[arg(short = 'j', long, default_value_t = 4)]
+        parallelism: usize,
     },
 
     /// Read records from a stream.
@@ -260,14 +362,64 @@ enum Commands {
         #[command(flatten)]
         args: BasinNameAndStreamArgs,
 
-        /// Starting sequence number (inclusive).
-        #[arg(short = 's', long, default_value_t = 0)]
-        start_seq_num: u64,
+        /// Starting sequence number (inclusive). Defaults to 0.
+        #[arg(short = 's', long, conflicts_with = "last")]
+        start_seq_num: Option<u64>,
+
+        /// Read the last N records on the stream, then stop.
+        ///
+        /// Fewer records are returned if the stream has been trimmed.
+        #[arg(long, value_name = "N")]
+        last: Option<u64>,
+
+        /// Keep following the stream for new records after the last N.
+        #[arg(short = 'f', long, requires = "last")]
+        follow: bool,
+
+        /// Save the position after each batch is written to this file, and
+        /// resume from it on startup.
+        ///
+        /// The starting sequence number only applies when the file does not
+        /// exist yet. The file is locked while reading, so it can't be shared
+        /// by two consumers.
+        #[arg(long, value_name = "FILE", conflicts_with = "last")]
+        checkpoint: Option<PathBuf>,
+
+        /// Ending sequence number (exclusive).
+        ///
+        /// Reading stops once a record at or beyond it is reached, even when
+        /// following the stream.
+        #[arg(short = 'e', long)]
+        end_seq_num: Option<u64>,
 
         /// Output records to a file or stdout.
         /// Use "-" to write to stdout.
-        #[arg(short = 'o', long, value_parser = parse_records_output_source, default_value = "-")]
-        output: RecordsOut,
+        ///
+        /// May be specified multiple times to write to several outputs at once.
+        /// Prefix with "text:" or "json:" to choose the format of an output,
+        /// e.g. "json:archive.jsonl". Other outputs use --format, --template or
+        /// --select.
+        ///
+        /// Defaults to stdout, unless --exec is given.
+        #[arg(short = 'o', long, value_name = "[FORMAT:]PATH")]
+        output: Vec<SinkSpec>,
+
+        /// Store records as rows of a table, e.g. "sqlite:records.db",
+        /// "parquet:records.parquet" or "arrow:records.arrow".
+        ///
+        /// Records get seq_num, headers and body columns. Command records are
+        /// not stored.
+        ///
+        /// SQLite databases get a `records` table, with a `headers_base64`
+        /// flag on rows whose headers aren't all UTF-8, and each batch is
+        /// inserted in one transaction. If the table already holds records, reading
+        /// resumes after the last one, and the starting sequence number only
+        /// applies when it is empty.
+        ///
+        /// Parquet and Arrow IPC files are only complete once reading stops,
+        /// so they need a limit, an ending sequence number or --last.
+        #[arg(long, value_name = "KIND:PATH")]
+        sink: Vec<TableSinkSpec>,
 
         /// Limit the number of records returned.
         #[arg(short = 'n', long)]
@@ -276,6 +428,189 @@ enum Commands {
         /// Limit the number of bytes returned.
         #[arg(short = 'b', long)]
         limit_bytes: Option<u64>,
+
+        /// Output format of records.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        /// Encoding of record bodies and headers in JSON output.
+        #[arg(long, value_enum, default_value_t = BodyEncoding::Auto)]
+        body_encoding: BodyEncoding,
+
+        /// How record bodies are shown in text output, and bodies and headers
+        /// in templates and non-JSON bodies passed through by '--select'.
+        ///
+        /// By default, bodies that aren't valid UTF-8 are escaped when writing
+        /// to a terminal, so binary data can't garble it.
+        #[arg(long, value_enum, default_value_t = BodyDisplay::Auto)]
+        display: BodyDisplay,
+
+        /// Render each record with a template instead of the output format,
+        /// e.g. '{seq_num}\t{header.host}\t{body}'.
+        ///
+        /// Placeholders: `{seq_num}`, `{header.<name>}`, `{body}`, `{body_len}`,
+        /// `{body_hex}` and `{body_base64}`. Use `{{` and `}}` for literal
+        /// braces, and `\t`, `\n` or `\\` for tabs, newlines and backslashes.
+        #[arg(long, value_name = "TEMPLATE", conflicts_with_all = ["format", "body_encoding"])]
+        template: Option<Template>,
+
+        /// Output only these fields of JSON record bodies, e.g. '.user.id, .event'.
+        ///
+        /// Paths start with `.` and are made of object keys and array indices
+        /// like `.tags[0]`. A single `.` selects the whole body.
+        #[arg(long, value_name = "PATHS", conflicts_with_all = ["format", "template"])]
+        select: Option<FieldSelection>,
+
+        /// Output format of selected fields.
+        #[arg(long, value_enum, default_value_t = SelectFormat::Json, requires = "select")]
+        select_format: SelectFormat,
+
+        /// What to do with records that aren't JSON when selecting fields.
+        #[arg(long, value_enum, default_value_t = NonJsonRecords::Skip, requires = "select")]
+        non_json: NonJsonRecords,
+
+        /// How to handle fence and trim command records.
+        ///
+        /// Defaults to inline for JSON output, and stderr otherwise.
+        #[arg(long, value_enum)]
+        commands: Option<CommandsMode>,
+
+        /// Only output records matching this predicate, e.g. 'header.level=error',
+        /// 'body~timeout' or 'len>1024'.
+        ///
+        /// Headers and bodies can be compared with = and !=, or matched against
+        /// a regex with ~ and !~. Body length can be compared with =, !=, >, >=,
+        /// < and <=. May be specified multiple times, in which case records
+        /// must match all predicates. Command records are not filtered.
+        #[arg(long = "where", value_name = "PREDICATE")]
+        where_: Vec<Predicate>,
+
+        /// Only output every Nth record, given as '1/N'.
+        ///
+        /// Sampling applies to records matching --where, starting with the
+        /// first. Command records are not sampled.
+        #[arg(long, value_name = "1/N", conflicts_with = "sample_rate")]
+        sample: Option<SampleEvery>,
+
+        /// Only output a random fraction of records, e.g. 0.01 for about one
+        /// in a hundred.
+        #[arg(long, value_name = "RATE")]
+        sample_rate: Option<SampleRate>,
+
+        /// Seed for --sample-rate, to pick the same records on every run.
+        #[arg(long, requires = "sample_rate")]
+        seed: Option<u64>,
+
+        /// Decrypt record bodies with the key in this file.
+        ///
+        /// May be specified multiple times to provide several keys, which are
+        /// picked by the `s2-key-id` record header. Records sealed with an
+        /// unknown key are reported and skipped.
+        #[arg(long, value_name = "KEYFILE")]
+        decrypt_key: Vec<PathBuf>,
+
+        /// Reconnect with exponential backoff if the read session fails,
+        /// resuming after the last record received.
+        #[arg(long)]
+        reconnect: bool,
+
+        /// Give up after this many consecutive failed reconnection attempts.
+        #[arg(
+            long,
+            value_name = "ATTEMPTS",
+            default_value_t = 10,
+            requires = "reconnect"
+        )]
+        reconnect_budget: u32,
+
+        /// Don't report throughput statistics on stderr.
+        #[arg(short = 'q', long, conflicts_with_all = ["stats_interval", "stats_format"])]
+        quiet: bool,
+
+        /// Report throughput statistics at this interval, rather than for
+        /// each batch.
+        #[arg(long, value_name = "DURATION")]
+        stats_interval: Option<humantime::Duration>,
+
+        /// Format of throughput statistics on stderr.
+        #[arg(long, value_enum, default_value_t = StatsFormat::Text)]
+        stats_format: StatsFormat,
+
+        #[command(flatten)]
+        rotate: RotateArgs,
+
+        #[command(flatten)]
+        exec: ExecArgs,
+
+        #[command(flatten)]
+        columnar: ColumnarArgs,
+    },
+
+    /// Browse records of a stream in an interactive terminal UI.
+    ///
+    /// Page through records by sequence number, jump to the head or tail,
+    /// inspect headers and bodies, search within the loaded page, and follow
+    /// new records live. Key bindings are shown at the bottom of the screen.
+    Browse {
+        #[command(flatten)]
+        args: BasinNameAndStreamArgs,
+
+        /// Sequence number of the first record to show. Defaults to the last
+        /// page of the stream.
+        #[arg(short = 's', long)]
+        start_seq_num: Option<u64>,
+
+        /// Number of records loaded at a time.
+        #[arg(short = 'n', long, default_value_t = 50)]
+        page_size: u64,
+    },
+
+    /// Follow several streams at once, with each line of output prefixed by
+    /// the name of its stream.
+    ///
+    /// Every stream is read from its own position, and read sessions that
+    /// fail are reconnected with backoff until interrupted. In JSON output,
+    /// each object gets a `stream` field instead of the prefix.
+    Tail {
+        /// S2 URIs of streams to follow, like 's2://basin/stream'.
+        #[arg(
+            value_name = "S2_URI",
+            required_unless_present = "prefix",
+            conflicts_with = "prefix"
+        )]
+        streams: Vec<BasinNameAndMaybeStreamUri>,
+
+        /// Follow all streams with names starting with a prefix, given as an
+        /// S2 URI like 's2://basin/prefix', or a basin name for all its streams.
+        #[arg(short = 'p', long, value_name = "BASIN|S2_URI")]
+        prefix: Option<BasinNameAndMaybeStreamUri>,
+
+        /// List streams under the prefix again at this interval, following
+        /// streams created since from their first record, and stopping for
+        /// streams that were deleted.
+        #[arg(
+            long,
+            value_name = "DURATION",
+            requires = "prefix",
+            conflicts_with = "streams"
+        )]
+        discover_interval: Option<humantime::Duration>,
+
+        /// Start this many records before the tail of each stream.
+        #[arg(short = 'n', long, default_value_t = 10)]
+        last: u64,
+
+        /// Output format of records.
+        #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        /// Encoding of record bodies and headers in JSON output.
+        #[arg(long, value_enum, default_value_t = BodyEncoding::Auto)]
+        body_encoding: BodyEncoding,
+
+        /// How record bodies are shown in text output.
+        #[arg(long, value_enum, default_value_t = BodyDisplay::Auto)]
+        display: BodyDisplay,
     },
 
     /// Ping the stream to get append acknowledgement and end-to-end latencies.
@@ -311,6 +646,20 @@ enum ConfigActions {
     },
 }
 
+/// Format of records in an append session.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Newline delimited records, one per line. Lines need not be UTF-8.
+    Text,
+    /// Comma separated values, one record per row.
+    Csv,
+    /// Tab separated values, one record per row.
+    Tsv,
+}
+
+/// Stream of records to append in an append session.
+type AppendRecordStream = Pin<Box<dyn Stream<Item = AppendRecord> + Send>>;
+
 #[derive(Debug, Clone)]
 pub enum RecordsIn {
     File(PathBuf),
@@ -327,33 +676,50 @@ pub enum RecordsOut {
 impl RecordsIn {
     pub async fn into_reader(
         &self,
-    ) -> std::io::Result<Pin<Box<dyn Stream<Item = std::io::Result<String>> + Send>>> {
-        match self {
-            RecordsIn::File(path) => {
-                let file = File::open(path).await?;
-                Ok(Box::pin(LinesStream::new(
-                    tokio::io::BufReader::new(file).lines(),
-                )))
-            }
-            RecordsIn::Stdin => Ok(Box::pin(stdio_lines_stream(std::io::stdin()))),
-        }
+    ) -> std::io::Result<Pin<Box<dyn Stream<Item = std::io::Result<Bytes>> + Send>>> {
+        let reader: Pin<Box<dyn AsyncRead + Send>> = match self {
+            RecordsIn::File(path) => Box::pin(File::open(path).await?),
+            RecordsIn::Stdin => Box::pin(stdin_reader()),
+        };
+        Ok(Box::pin(FramedRead::new(
+            reader,
+            NewlineDelimitedCodec::default(),
+        )))
     }
 }
 
-fn stdio_lines_stream<F>(f: F) -> ReceiverStream<std::io::Result<String>>
-where
-    F: std::io::Read + Send + 'static,
-{
-    let lines = std::io::BufReader::new(f).lines();
-    let (tx, rx) = mpsc::channel(AppendRecordBatch::MAX_CAPACITY);
+/// Async reader over stdin, fed in large chunks by a dedicated thread.
+///
+/// Unlike `tokio::io::stdin()`, a pending blocking read does not hold up
+/// runtime shutdown, so aborting an interactive append exits right away.
+///
+/// Reads fill a shared buffer that is only replaced once mostly used up, with
+/// each chunk split off it without copying.
+fn stdin_reader() -> impl AsyncRead + Send {
+    const BUFFER_BYTES: usize = 1024 * 1024;
+    const MIN_READ_BYTES: usize = 64 * 1024;
+
+    let (tx, rx) = mpsc::channel(4);
     let _handle = std::thread::spawn(move || {
-        for line in lines {
-            if tx.blocking_send(line).is_err() {
+        let mut stdin = std::io::stdin().lock();
+        let mut buf = BytesMut::zeroed(BUFFER_BYTES);
+        loop {
+            if buf.len() < MIN_READ_BYTES {
+                buf = BytesMut::zeroed(BUFFER_BYTES);
+            }
+            let res = match stdin.read(&mut buf) {
+                Ok(0) => return,
+                Ok(n) => Ok(buf.split_to(n).freeze()),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            let is_err = res.is_err();
+            if tx.blocking_send(res).is_err() || is_err {
                 return;
             }
         }
     });
-    ReceiverStream::new(rx)
+    StreamReader::new(ReceiverStream::new(rx))
 }
 
 impl RecordsOut {
@@ -702,19 +1068,81 @@ async fn run() -> Result<(), S2CliError> {
         Commands::Append {
             args,
             input,
+            format,
+            csv,
+            encrypt_key,
+            schema,
+            on_invalid,
+            dead_letter,
             fencing_token,
             match_seq_num,
         } => {
+            let encrypt_key = encrypt_key.as_deref().map(RecordKey::load).transpose()?;
+            let schema_validator = match &schema {
+                Some(schema) => {
+                    let source_name = match &input {
+                        RecordsIn::File(path) => path.display().to_string(),
+                        RecordsIn::Stdin => "stdin".to_string(),
+                    };
+                    Some(SchemaValidator::load(schema, source_name)?)
+                }
+                None => None,
+            };
+            let dead_letter = dead_letter
+                .map(|path| {
+                    std::fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(path)
+                        .map_err(|e| S2CliError::RecordWrite(e.to_string()))
+                })
+                .transpose()?;
             let (basin, stream) = args.try_into_parts()?;
             let cfg = config::load_config(&config_path)?;
             let client_config = client_config(cfg.auth_token)?;
             let stream_client = StreamClient::new(client_config, basin, stream);
-            let append_input_stream = RecordStream::new(
-                input
-                    .into_reader()
-                    .await
-                    .map_err(|e| S2CliError::RecordReaderInit(e.to_string()))?,
-            );
+            let (read_error_tx, mut read_error_rx) = oneshot::channel();
+            let append_input_stream: AppendRecordStream = match format {
+                InputFormat::Text => Box::pin(until_error(
+                    RecordStream::new(
+                        input
+                            .into_reader()
+                            .await
+                            .map_err(|e| S2CliError::RecordReaderInit(e.to_string()))?,
+                    ),
+                    read_error_tx,
+                )),
+                InputFormat::Csv | InputFormat::Tsv => {
+                    let default_delimiter = if format == InputFormat::Tsv {
+                        b'\t'
+                    } else {
+                        b','
+                    };
+                    Box::pin(until_error(
+                        csv_records_stream(&input, csv, default_delimiter).await?,
+                        read_error_tx,
+                    ))
+                }
+            };
+            let (rejected_tx, mut rejected_rx) = oneshot::channel();
+            let append_input_stream: AppendRecordStream = match schema_validator {
+                Some(validator) => Box::pin(validate_records(
+                    append_input_stream,
+                    validator,
+                    on_invalid,
+                    dead_letter,
+                    rejected_tx,
+                )),
+                None => append_input_stream,
+            };
+            let (encrypt_error_tx, mut encrypt_error_rx) = oneshot::channel();
+            let append_input_stream: AppendRecordStream = match encrypt_key {
+                Some(key) => Box::pin(until_error(
+                    append_input_stream.map(move |record| key.encrypt(record)),
+                    encrypt_error_tx,
+                )),
+                None => append_input_stream,
+            };
 
             let mut append_output_stream = StreamService::new(stream_client)
                 .append_session(
@@ -760,131 +1188,273 @@ async fn run() -> Result<(), S2CliError> {
                     }
                 }
             }
+
+            if let Ok(e) = read_error_rx.try_recv() {
+                return Err(S2CliError::RecordRead(e));
+            }
+            if let Ok(e) = encrypt_error_rx.try_recv() {
+                return Err(S2CliError::RecordEncrypt(e));
+            }
+            if let Ok(violation) = rejected_rx.try_recv() {
+                return Err(violation.into());
+            }
+        }
+
+        Commands::BulkAppend {
+            manifest,
+            parallelism,
+        } => {
+            let entries = bulk::load_manifest(&manifest)?;
+            let cfg = config::load_config(&config_path)?;
+            let client_config = client_config(cfg.auth_token)?;
+
+            select! {
+                res = bulk::bulk_append(client_config, entries, parallelism) => res?,
+                _ = signal::ctrl_c() => {
+                    eprintln!("{}", "■ [ABORTED]".red().bold());
+                }
+            }
         }
 
         Commands::Read {
             args,
             start_seq_num,
+            last,
+            follow,
+            checkpoint,
+            end_seq_num,
             output,
+            sink,
             limit_count,
             limit_bytes,
+            format,
+            body_encoding,
+            display,
+            template,
+            select,
+            select_format,
+            non_json,
+            commands,
+            where_,
+            sample,
+            sample_rate,
+            seed,
+            decrypt_key,
+            reconnect,
+            reconnect_budget,
+            quiet,
+            stats_interval,
+            stats_format,
+            rotate,
+            exec,
+            columnar,
         } => {
+            let bounded = limit_count.is_some()
+                || limit_bytes.is_some()
+                || end_seq_num.is_some()
+                || (last.is_some() && !follow);
+            if !bounded && sink.iter().any(TableSinkSpec::needs_bounded_read) {
+                return Err(S2CliError::InvalidArgs(miette::miette!(
+                    help = "Use '--limit-count', '--limit-bytes', '--end-seq-num' or '--last'",
+                    "Parquet and Arrow outputs need a bounded read"
+                )));
+            }
+            let file_outputs = output.iter().filter(|spec| spec.is_file()).count();
+            if rotate.enabled() && file_outputs != 1 {
+                return Err(S2CliError::InvalidArgs(miette::miette!(
+                    help = "Give exactly one file output to rotate into, with any others on stdout",
+                    "Output rotation requires a single output directory, got {file_outputs} file outputs"
+                )));
+            }
+            if last.is_some() && sink.iter().any(TableSinkSpec::resumes) {
+                return Err(S2CliError::InvalidArgs(miette::miette!(
+                    help = "Reading resumes after the records already stored in SQLite",
+                    "'--last' can't be used with a SQLite sink"
+                )));
+            }
+            let sampler = match (sample, sample_rate) {
+                (Some(every), _) => Some(Sampler::every(every)),
+                (None, Some(rate)) => Some(Sampler::rate(rate, seed)),
+                (None, None) => None,
+            };
+            let filter = RecordFilter::new(where_).with_sampler(sampler);
+            let projection = select.map(|fields| Projection {
+                fields,
+                format: select_format,
+                non_json,
+            });
+            let formatter = RecordFormatter::new(format, body_encoding)
+                .with_display(display)
+                .with_template(template)
+                .with_projection(projection);
+            let keyring = if decrypt_key.is_empty() {
+                None
+            } else {
+                Some(RecordKeyring::load(&decrypt_key)?)
+            };
             let (basin, stream) = args.try_into_parts()?;
+            let (checkpoint, checkpoint_seq_num) = match checkpoint {
+                Some(path) => {
+                    let (checkpoint, next_seq_num) =
+                        Checkpoint::open(&path, basin.to_string(), stream.clone())?;
+                    (Some(checkpoint), next_seq_num)
+                }
+                None => (None, None),
+            };
+            let table_seq_num = Sinks::resume_seq_num(&sink)?;
+            // The checkpoint is only saved once every sink has taken the
+            // records before it, so it never resumes past a table.
+            let bounds = ReadBounds {
+                start_seq_num: checkpoint_seq_num.or(table_seq_num).or(start_seq_num),
+                last,
+                follow,
+                end_seq_num,
+                limit_count,
+                limit_bytes,
+            };
+            let resuming = checkpoint.is_some() || table_seq_num.is_some();
             let cfg = config::load_config(&config_path)?;
             let client_config = client_config(cfg.auth_token)?;
             let stream_client = StreamClient::new(client_config, basin, stream);
-            let mut read_output_stream = StreamService::new(stream_client)
-                .read_session(start_seq_num, limit_count, limit_bytes)
+            let session = ReadSession::start(
+                StreamService::new(stream_client),
+                bounds,
+                resuming,
+                reconnect.then_some(reconnect_budget),
+            )
+            .await?;
+
+            // Outputs are only opened once reading starts, so that failing to
+            // start doesn't leave partial files behind.
+            let sinks = Sinks::open(
+                output, sink, &formatter, commands, &rotate, &exec, &columnar,
+            )
+            .await?;
+            let outputs = ReadOutputs {
+                sinks,
+                filter,
+                keyring,
+                checkpoint,
+            };
+            session
+                .run(
+                    outputs,
+                    ReadStats::new(stats_format),
+                    stats_interval.map(|interval| *interval),
+                    quiet,
+                )
                 .await?;
-            let mut writer = output.into_writer().await.unwrap();
+        }
+
+        Commands::Browse {
+            args,
+            start_seq_num,
+            page_size,
+        } => {
+            let (basin, stream) = args.try_into_parts()?;
+            let label = format!("s2://{basin}/{stream}");
+            let cfg = config::load_config(&config_path)?;
+            let client_config = client_config(cfg.auth_token)?;
+            let stream_service =
+                StreamService::new(StreamClient::new(client_config, basin, stream));
+            browse::run(stream_service, label, start_seq_num, page_size.max(1)).await?;
+        }
 
-            let mut start = None;
-            let mut total_data_len = 0;
+        Commands::Tail {
+            streams,
+            prefix,
+            discover_interval,
+            last,
+            format,
+            body_encoding,
+            display,
+        } => {
+            let cfg = config::load_config(&config_path)?;
+            let client_config = client_config(cfg.auth_token)?;
+            let formatter = RecordFormatter::new(format, body_encoding)
+                .with_display(display)
+                .for_terminal(std::io::stdout().is_terminal());
+            let mut tailer = Tailer::new(formatter);
+
+            let discovery = match prefix {
+                Some(BasinNameAndMaybeStreamUri { basin, stream }) => {
+                    let prefix = stream.unwrap_or_default();
+                    let basin_client = BasinClient::new(client_config, basin.clone());
+                    let discovery = StreamDiscovery::new(basin_client, prefix.clone());
+                    let found = discovery.sync(&mut tailer, TailStart::Last(last)).await?;
+                    if found.followed.is_empty() && discover_interval.is_none() {
+                        return Err(S2CliError::InvalidArgs(miette::miette!(
+                            help = "Check the basin name and prefix with 's2 ls', or use '--discover-interval' to wait for streams to be created",
+                            "No streams found with prefix '{prefix}' in basin '{basin}'"
+                        )));
+                    }
+                    Some(discovery)
+                }
+                None => {
+                    let streams = streams
+                        .into_iter()
+                        .map(|uri| match uri.stream {
+                            Some(stream) => Ok((uri.basin, stream)),
+                            None => Err(S2CliError::InvalidArgs(miette::miette!(
+                                help = "Provide streams as URIs like 's2://basin/stream', or use '--prefix' to follow all streams in a basin",
+                                "Missing stream name in URI for basin '{}'",
+                                uri.basin
+                            ))),
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let single_basin = streams
+                        .iter()
+                        .all(|(basin, _)| basin.as_ref() == streams[0].0.as_ref());
+                    let mut basin_clients: HashMap<String, BasinClient> = HashMap::new();
+                    for (basin, stream) in streams {
+                        let label = if single_basin {
+                            stream.clone()
+                        } else {
+                            format!("{basin}/{stream}")
+                        };
+                        if tailer.is_following(&label) {
+                            continue;
+                        }
+                        let basin_client = basin_clients
+                            .entry(basin.to_string())
+                            .or_insert_with(|| BasinClient::new(client_config.clone(), basin));
+                        tailer.follow(basin_client, stream, label, TailStart::Last(last));
+                    }
+                    None
+                }
+            };
 
+            let mut discover_interval = discover_interval.map(|interval| {
+                let interval = *interval;
+                tokio::time::interval_at(Instant::now() + interval, interval)
+            });
+            let mut stdout = tokio::io::stdout();
             loop {
                 select! {
-                    maybe_read_result = read_output_stream.next() => {
-                        match maybe_read_result {
-                            Some(read_result) => {
-                                if start.is_none() {
-                                    start = Some(Instant::now());
+                    event = tailer.recv() => tailer.write(event, &mut stdout).await?,
+                    _ = tick(&mut discover_interval) => {
+                        let discovery = discovery.as_ref().expect("discovery with prefix");
+                        // Streams created since the last listing are read from
+                        // the start, so their first records aren't missed.
+                        match discovery.sync(&mut tailer, TailStart::SeqNum(0)).await {
+                            Ok(DiscoveredStreams { followed, unfollowed }) => {
+                                for stream in followed {
+                                    eprintln!("{}", format!("+ Following new stream {stream}").green().bold());
                                 }
-                                match read_result {
-                                    Ok(ReadOutput::Batch(sequenced_record_batch)) => {
-                                        let num_records = sequenced_record_batch.records.len();
-                                        let mut batch_len = 0;
-
-                                        let seq_range = match (
-                                            sequenced_record_batch.records.first(),
-                                            sequenced_record_batch.records.last(),
-                                        ) {
-                                            (Some(first), Some(last)) => first.seq_num..=last.seq_num,
-                                            _ => panic!("empty batch"),
-                                        };
-                                        for sequenced_record in sequenced_record_batch.records {
-                                            batch_len += sequenced_record.metered_bytes();
-
-                                            if let Some(command_record) = sequenced_record.as_command_record() {
-                                                let (cmd, description) = match command_record {
-                                                    CommandRecord::Fence { fencing_token } => (
-                                                        "fence",
-                                                        format!("FencingToken({})", base16ct::lower::encode_string(fencing_token.as_ref())),
-                                                    ),
-                                                    CommandRecord::Trim { seq_num } => (
-                                                        "trim",
-                                                        format!("TrimPoint({seq_num})"),
-                                                    ),
-                                                };
-                                                eprintln!("{} with {}", cmd.bold(), description.green().bold());
-                                            } else {
-                                                let data = &sequenced_record.body;
-                                                writer
-                                                    .write_all(data)
-                                                    .await
-                                                    .map_err(|e| S2CliError::RecordWrite(e.to_string()))?;
-                                                writer
-                                                    .write_all(b"\n")
-                                                    .await
-                                                    .map_err(|e| S2CliError::RecordWrite(e.to_string()))?;
-                                            }
-                                        }
-                                        total_data_len += batch_len;
-
-                                        let throughput_mibps = (total_data_len as f64
-                                            / start.unwrap().elapsed().as_secs_f64())
-                                            / 1024.0
-                                            / 1024.0;
-
-                                        eprintln!(
-                                            "{}",
-                                            format!(
-                                                "⦿ {throughput_mibps:.2} MiB/s \
-                                                    ({num_records} records in range {seq_range:?})",
-                                            )
-                                            .blue()
-                                            .bold()
-                                        );
-                                    }
-
-                                    Ok(ReadOutput::FirstSeqNum(seq_num)) => {
-                                        eprintln!("{}", format!("first_seq_num: {seq_num}").blue().bold());
-                                    }
-
-                                    Ok(ReadOutput::NextSeqNum(seq_num)) => {
-                                        eprintln!("{}", format!("next_seq_num: {seq_num}").blue().bold());
-                                    }
-
-                                    Err(e) => {
-                                        return Err(ServiceError::new(ServiceErrorContext::ReadSession, e).into());
-                                    }
+                                for stream in unfollowed {
+                                    eprintln!("{}", format!("- Stopped following deleted stream {stream}").yellow().bold());
                                 }
                             }
-                            None => break,
+                            Err(e) => {
+                                eprintln!("{}", format!("⚠ Failed to discover streams: {e}").yellow().bold());
+                            }
                         }
-                    },
+                    }
                     _ = signal::ctrl_c() => {
-                        drop(read_output_stream);
                         eprintln!("{}", "■ [ABORTED]".red().bold());
                         break;
                     }
                 }
-                let total_elapsed_time = start.unwrap().elapsed().as_secs_f64();
-
-                let total_throughput_mibps =
-                    (total_data_len as f64 / total_elapsed_time) / 1024.0 / 1024.0;
-
-                eprintln!(
-                    "{}",
-                    format!(
-                        "{total_data_len} metered bytes in \
-                                {total_elapsed_time} seconds \
-                                at {total_throughput_mibps:.2} MiB/s"
-                    )
-                    .yellow()
-                    .bold()
-                );
-
-                writer.flush().await.expect("writer flush");
             }
         }
 
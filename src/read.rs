@@ -0,0 +1,446 @@
+//! Reading a stream into the outputs of `s2 read`.
+//!
+//! A read session is opened at the start position and bounded by the limits
+//! and end of the read. The position and what's left of the limits are kept
+//! up to date as batches are written, so that the session can be reopened
+//! from where it failed with `--reconnect`, or from the first record if the
+//! start was trimmed.
+
+use std::time::Duration;
+
+use colored::Colorize;
+use futures::StreamExt;
+use s2::{
+    types::{CommandRecord, MeteredBytes as _, ReadOutput, SequencedRecord},
+    Streaming,
+};
+use tokio::{select, signal, time::Instant};
+
+use crate::{
+    backoff,
+    checkpoint::Checkpoint,
+    crypto::RecordKeyring,
+    error::{S2CliError, ServiceError, ServiceErrorContext},
+    filter::RecordFilter,
+    sink::{BatchItem, Sinks},
+    stats::ReadStats,
+    stream::StreamService,
+    tick, RECONNECT_BACKOFF_BASE, RECONNECT_BACKOFF_MAX,
+};
+
+/// Which records of a stream to read.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadBounds {
+    pub start_seq_num: Option<u64>,
+    /// Read the last N records instead, up to the tail unless following.
+    pub last: Option<u64>,
+    pub follow: bool,
+    pub end_seq_num: Option<u64>,
+    pub limit_count: Option<u64>,
+    pub limit_bytes: Option<u64>,
+}
+
+/// Position of a read and what's left of its limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ReadPosition {
+    next_seq_num: u64,
+    /// Records from here on aren't read. This is `--end-seq-num`, or the
+    /// tail when reading the last N records without following.
+    end_seq_num: Option<u64>,
+    remaining_count: Option<u64>,
+    remaining_bytes: Option<u64>,
+}
+
+impl ReadPosition {
+    fn at_end(&self) -> bool {
+        self.end_seq_num.is_some_and(|end| self.next_seq_num >= end)
+    }
+
+    /// Whether there's nothing left to read, as the end or a limit was
+    /// reached.
+    fn done(&self) -> bool {
+        self.at_end() || self.remaining_count == Some(0) || self.remaining_bytes == Some(0)
+    }
+
+    /// Count limit of a session opened at the position. It's also limited to
+    /// the records before the end, so the session doesn't keep following if
+    /// the tail is at the end. The position must be before the end, as a zero
+    /// limit means no limit.
+    fn session_limit_count(&self) -> Option<u64> {
+        match self.end_seq_num {
+            Some(end) => {
+                let count = end - self.next_seq_num;
+                Some(self.remaining_count.map_or(count, |c| c.min(count)))
+            }
+            None => self.remaining_count,
+        }
+    }
+
+    fn advance(&mut self, next_seq_num: u64, records: usize, metered_bytes: u64) {
+        self.next_seq_num = next_seq_num;
+        self.remaining_count = self
+            .remaining_count
+            .map(|c| c.saturating_sub(records as u64));
+        self.remaining_bytes = self
+            .remaining_bytes
+            .map(|b| b.saturating_sub(metered_bytes));
+    }
+}
+
+/// Where the records read go.
+pub struct ReadOutputs {
+    pub sinks: Sinks,
+    pub filter: RecordFilter,
+    pub keyring: Option<RecordKeyring>,
+    pub checkpoint: Option<Checkpoint>,
+}
+
+impl ReadOutputs {
+    /// Decrypt and filter a batch of records, and write it to every sink.
+    async fn write_batch(&mut self, records: Vec<SequencedRecord>) -> Result<(), S2CliError> {
+        let mut items = Vec::with_capacity(records.len());
+        for record in records {
+            if let Some(command) = record.as_command_record() {
+                if self.sinks.reports_commands() {
+                    let (cmd, description) = match &command {
+                        CommandRecord::Fence { fencing_token } => (
+                            "fence",
+                            format!(
+                                "FencingToken({})",
+                                base16ct::lower::encode_string(fencing_token.as_ref())
+                            ),
+                        ),
+                        CommandRecord::Trim { seq_num } => {
+                            ("trim", format!("TrimPoint({seq_num})"))
+                        }
+                    };
+                    eprintln!("{} with {}", cmd.bold(), description.green().bold());
+                }
+                items.push(BatchItem::Command {
+                    seq_num: record.seq_num,
+                    command,
+                });
+                continue;
+            }
+            let record = match &self.keyring {
+                Some(keyring) => {
+                    let seq_num = record.seq_num;
+                    match keyring.decrypt(record) {
+                        Ok(record) => record,
+                        Err(e) => {
+                            eprintln!(
+                                "{}",
+                                format!("✗ [UNDECRYPTABLE] seq_num={seq_num}: {e}")
+                                    .red()
+                                    .bold()
+                            );
+                            continue;
+                        }
+                    }
+                }
+                None => record,
+            };
+            if self.filter.matches(&record) {
+                items.push(BatchItem::Record(record));
+            }
+        }
+        self.sinks.write_batch(&items).await
+    }
+
+    /// Save the checkpoint, if any, once the sinks have taken the records
+    /// before `next_seq_num`.
+    async fn save_checkpoint(&mut self, next_seq_num: u64) -> Result<(), S2CliError> {
+        if let Some(checkpoint) = &self.checkpoint {
+            self.sinks.flush().await?;
+            checkpoint.save(self.sinks.committed_seq_num(next_seq_num))?;
+        }
+        Ok(())
+    }
+}
+
+/// How reading stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ReadEnd {
+    /// Every record within the bounds was read.
+    Done,
+    Aborted,
+}
+
+/// A read session of a stream, reopened as needed until the read is done.
+pub struct ReadSession {
+    stream_service: StreamService,
+    stream: Streaming<ReadOutput>,
+    position: ReadPosition,
+    /// Whether to read from the first record if the start was trimmed,
+    /// rather than reporting the first sequence number.
+    from_first: bool,
+    resuming: bool,
+    /// Reconnection attempts allowed in a row, if reconnecting.
+    reconnect_budget: Option<u32>,
+    reconnect_attempts: u32,
+}
+
+impl ReadSession {
+    /// Open a session for the records within `bounds`. The start is a
+    /// resumed position if `resuming`.
+    pub async fn start(
+        stream_service: StreamService,
+        bounds: ReadBounds,
+        resuming: bool,
+        reconnect_budget: Option<u32>,
+    ) -> Result<Self, S2CliError> {
+        let (start_seq_num, end_seq_num) = match bounds.last {
+            Some(last) => {
+                let tail = stream_service.check_tail().await?;
+                let end_seq_num = if bounds.follow {
+                    bounds.end_seq_num
+                } else {
+                    Some(bounds.end_seq_num.map_or(tail, |end| end.min(tail)))
+                };
+                (tail.saturating_sub(last), end_seq_num)
+            }
+            None => (bounds.start_seq_num.unwrap_or(0), bounds.end_seq_num),
+        };
+        let position = ReadPosition {
+            next_seq_num: start_seq_num,
+            end_seq_num,
+            remaining_count: bounds.limit_count,
+            remaining_bytes: bounds.limit_bytes,
+        };
+        let stream: Streaming<ReadOutput> = if position.at_end() {
+            // There's nothing to read, and a zero limit would mean no limit,
+            // so skip the session.
+            Box::pin(futures::stream::empty())
+        } else {
+            Self::open(&stream_service, &position).await?
+        };
+        Ok(Self {
+            stream_service,
+            stream,
+            position,
+            from_first: bounds.last.is_some() || resuming,
+            resuming,
+            reconnect_budget,
+            reconnect_attempts: 0,
+        })
+    }
+
+    async fn open(
+        stream_service: &StreamService,
+        position: &ReadPosition,
+    ) -> Result<Streaming<ReadOutput>, ServiceError> {
+        stream_service
+            .read_session(
+                position.next_seq_num,
+                position.session_limit_count(),
+                position.remaining_bytes,
+            )
+            .await
+    }
+
+    /// Read into `outputs` until the read is done or aborted, then close
+    /// them. Outputs that need the whole range are left incomplete if the
+    /// read is aborted or fails.
+    pub async fn run(
+        mut self,
+        mut outputs: ReadOutputs,
+        mut stats: ReadStats,
+        stats_interval: Option<Duration>,
+        quiet: bool,
+    ) -> Result<(), S2CliError> {
+        let end = self
+            .read_into(&mut outputs, &mut stats, stats_interval, quiet)
+            .await?;
+        drop(self.stream);
+
+        match end {
+            ReadEnd::Done => outputs.sinks.close().await?,
+            ReadEnd::Aborted => outputs.sinks.abort().await?,
+        }
+        if let Some(checkpoint) = &outputs.checkpoint {
+            checkpoint.save(outputs.sinks.committed_seq_num(self.position.next_seq_num))?;
+        }
+        if !quiet {
+            stats.print_summary(&outputs.filter);
+        }
+        Ok(())
+    }
+
+    async fn read_into(
+        &mut self,
+        outputs: &mut ReadOutputs,
+        stats: &mut ReadStats,
+        stats_interval: Option<Duration>,
+        quiet: bool,
+    ) -> Result<ReadEnd, S2CliError> {
+        let mut stats_interval = stats_interval
+            .map(|interval| tokio::time::interval_at(Instant::now() + interval, interval));
+
+        loop {
+            select! {
+                maybe_read_result = self.stream.next() => {
+                    match maybe_read_result {
+                        Some(Ok(ReadOutput::Batch(batch))) => {
+                            let mut records = batch.records;
+                            let mut reached_end = false;
+                            if let Some(end_seq_num) = self.position.end_seq_num {
+                                let end_idx = records.partition_point(|r| r.seq_num < end_seq_num);
+                                reached_end = end_idx < records.len();
+                                records.truncate(end_idx);
+                                if records.is_empty() {
+                                    return Ok(ReadEnd::Done);
+                                }
+                            }
+
+                            let seq_range = match (records.first(), records.last()) {
+                                (Some(first), Some(last)) => first.seq_num..=last.seq_num,
+                                _ => panic!("empty batch"),
+                            };
+                            let num_records = records.len();
+                            let batch_len = records.iter().map(|r| r.metered_bytes()).sum();
+                            outputs.write_batch(records).await?;
+                            self.position.advance(seq_range.end() + 1, num_records, batch_len);
+                            self.reconnect_attempts = 0;
+                            outputs.save_checkpoint(self.position.next_seq_num).await?;
+                            stats.record_batch(num_records, batch_len);
+                            if !quiet && stats_interval.is_none() {
+                                stats.print_batch(num_records, batch_len, &seq_range);
+                            }
+
+                            if reached_end || self.position.at_end() {
+                                return Ok(ReadEnd::Done);
+                            }
+                        }
+
+                        Some(Ok(ReadOutput::FirstSeqNum(seq_num))) if self.from_first => {
+                            // The stream was trimmed past the start of the last N
+                            // records or the resumed position, so read from the
+                            // first record instead.
+                            if self.resuming {
+                                eprintln!(
+                                    "{}",
+                                    format!("Resume position trimmed, resuming from seq_num={seq_num}")
+                                        .yellow()
+                                        .bold()
+                                );
+                            }
+                            self.position.next_seq_num = seq_num;
+                            if self.position.at_end() {
+                                return Ok(ReadEnd::Done);
+                            }
+                            self.stream = Self::open(&self.stream_service, &self.position).await?;
+                        }
+
+                        Some(Ok(ReadOutput::FirstSeqNum(seq_num))) => {
+                            eprintln!("{}", format!("first_seq_num: {seq_num}").blue().bold());
+                        }
+
+                        Some(Ok(ReadOutput::NextSeqNum(seq_num))) => {
+                            eprintln!("{}", format!("next_seq_num: {seq_num}").blue().bold());
+                        }
+
+                        Some(Err(e)) => {
+                            let error = ServiceError::new(ServiceErrorContext::ReadSession, e);
+                            if let Some(end) = self.reconnect(error).await? {
+                                return Ok(end);
+                            }
+                        }
+
+                        None => return Ok(ReadEnd::Done),
+                    }
+                },
+                _ = signal::ctrl_c() => {
+                    eprintln!("{}", "■ [ABORTED]".red().bold());
+                    return Ok(ReadEnd::Aborted);
+                }
+                _ = Sinks::rotation_due(outputs.sinks.rotation_deadline()) => {
+                    outputs.sinks.rotate_due().await?;
+                }
+                _ = tick(&mut stats_interval) => {
+                    stats.print_progress(&outputs.filter);
+                }
+            }
+            outputs.sinks.flush().await?;
+        }
+    }
+
+    /// Reopen the session at the position after it failed with `error`,
+    /// backing off between attempts. Returns how reading ended instead if
+    /// the read was already done or is aborted.
+    async fn reconnect(&mut self, mut error: ServiceError) -> Result<Option<ReadEnd>, S2CliError> {
+        loop {
+            if self.position.done() {
+                // The read was done before the session failed.
+                return Ok(Some(ReadEnd::Done));
+            }
+            let budget = match self.reconnect_budget {
+                Some(budget) if self.reconnect_attempts < budget => budget,
+                _ => return Err(error.into()),
+            };
+            self.reconnect_attempts += 1;
+            let attempts = self.reconnect_attempts;
+            let backoff = backoff(attempts, RECONNECT_BACKOFF_BASE, RECONNECT_BACKOFF_MAX);
+            eprintln!(
+                "{}",
+                format!(
+                    "⟳ Reconnecting from seq_num={} in {} (attempt {attempts}/{budget}) after {error}",
+                    self.position.next_seq_num,
+                    humantime::format_duration(backoff),
+                )
+                .yellow()
+                .bold()
+            );
+            select! {
+                _ = tokio::time::sleep(backoff) => {}
+                _ = signal::ctrl_c() => {
+                    eprintln!("{}", "■ [ABORTED]".red().bold());
+                    return Ok(Some(ReadEnd::Aborted));
+                }
+            }
+            match Self::open(&self.stream_service, &self.position).await {
+                Ok(stream) => {
+                    self.stream = stream;
+                    return Ok(None);
+                }
+                Err(e) => error = e,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_position() {
+        let mut position = ReadPosition {
+            next_seq_num: 10,
+            end_seq_num: Some(15),
+            remaining_count: Some(20),
+            remaining_bytes: None,
+        };
+        assert_eq!(position.session_limit_count(), Some(5));
+        assert!(!position.done());
+
+        position.advance(13, 3, 100);
+        assert_eq!(position.remaining_count, Some(17));
+        assert_eq!(position.session_limit_count(), Some(2));
+        position.remaining_count = Some(1);
+        assert_eq!(position.session_limit_count(), Some(1));
+
+        position.advance(15, 2, 100);
+        assert!(position.at_end());
+        assert!(position.done());
+
+        let position = ReadPosition {
+            next_seq_num: 0,
+            end_seq_num: None,
+            remaining_count: None,
+            remaining_bytes: Some(0),
+        };
+        assert_eq!(position.session_limit_count(), None);
+        assert!(!position.at_end());
+        assert!(position.done());
+    }
+}
@@ -0,0 +1,384 @@
+//! Rotating file output for read sessions.
+//!
+//! Records are written to segment files in an output directory. A segment is
+//! closed once it reaches a size, record count or age limit, renamed after the
+//! sequence numbers it holds, optionally compressed, and added to an index.
+
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use clap::{ArgGroup, Args};
+use serde::Serialize;
+use tokio::{
+    fs::{File, OpenOptions},
+    io::{AsyncWriteExt, BufWriter},
+    time::Instant,
+};
+
+use crate::error::S2CliError;
+
+/// Name of the index file in the output directory, with one JSON line per
+/// closed segment.
+pub const INDEX_FILE_NAME: &str = "index.jsonl";
+
+/// Template for segment file names, like `{first_seq_num}-{last_seq_num}.log`.
+///
+/// Supported placeholders are `first_seq_num`, `last_seq_num` and `timestamp`,
+/// the UTC time the segment was opened in `YYYYMMDDTHHMMSSZ` format. At least
+/// one of the sequence number placeholders is required.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentName(String);
+
+impl FromStr for SegmentName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() || s.contains(std::path::is_separator) {
+            return Err("segment name must be a non-empty file name".to_string());
+        }
+
+        let mut rest = s;
+        while let Some(open) = rest.find('{') {
+            let close = rest[open..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed '{{' in '{s}'"))?;
+            let placeholder = &rest[open + 1..open + close];
+            if !["first_seq_num", "last_seq_num", "timestamp"].contains(&placeholder) {
+                return Err(format!(
+                    "unknown placeholder '{{{placeholder}}}', expected one of \
+                     {{first_seq_num}}, {{last_seq_num}} or {{timestamp}}"
+                ));
+            }
+            rest = &rest[open + close + 1..];
+        }
+        // Segments are told apart by the records they hold, so that a rename
+        // never replaces an earlier one.
+        if !s.contains("{first_seq_num}") && !s.contains("{last_seq_num}") {
+            return Err(format!(
+                "'{s}' must include {{first_seq_num}} or {{last_seq_num}} to name each segment uniquely"
+            ));
+        }
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl SegmentName {
+    fn render(&self, first_seq_num: u64, last_seq_num: u64, opened_at: SystemTime) -> String {
+        let timestamp: String = humantime::format_rfc3339_seconds(opened_at)
+            .to_string()
+            .chars()
+            .filter(|c| !matches!(c, '-' | ':'))
+            .collect();
+        self.0
+            .replace("{first_seq_num}", &first_seq_num.to_string())
+            .replace("{last_seq_num}", &last_seq_num.to_string())
+            .replace("{timestamp}", &timestamp)
+    }
+}
+
+#[derive(Args, Debug, Clone)]
+#[command(
+    next_help_heading = "Output rotation",
+    group(
+        ArgGroup::new("rotate_trigger")
+            .args(["rotate_bytes", "rotate_records", "rotate_interval"])
+            .multiple(true)
+    )
+)]
+pub struct RotateArgs {
+    /// Rotate to a new output file once the current one holds this many bytes.
+    ///
+    /// Rotation requires a single file output, which is used as a directory
+    /// and created if needed.
+    #[arg(long, value_name = "BYTES")]
+    pub rotate_bytes: Option<u64>,
+
+    /// Rotate to a new output file once the current one holds this many records.
+    #[arg(long, value_name = "COUNT")]
+    pub rotate_records: Option<u64>,
+
+    /// Rotate to a new output file once the current one has been open this long.
+    #[arg(long, value_name = "DURATION")]
+    pub rotate_interval: Option<humantime::Duration>,
+
+    /// Name of output files, with placeholders {first_seq_num}, {last_seq_num}
+    /// and {timestamp}. Must include {first_seq_num} or {last_seq_num}.
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        default_value = "{first_seq_num}-{last_seq_num}.out",
+        requires = "rotate_trigger"
+    )]
+    pub rotate_name: SegmentName,
+
+    /// Compress output files with gzip once they are rotated.
+    #[arg(long, requires = "rotate_trigger")]
+    pub rotate_gzip: bool,
+}
+
+impl RotateArgs {
+    pub fn enabled(&self) -> bool {
+        self.rotate_bytes.is_some()
+            || self.rotate_records.is_some()
+            || self.rotate_interval.is_some()
+    }
+}
+
+#[derive(Serialize)]
+struct IndexEntry<'a> {
+    file: &'a str,
+    first_seq_num: u64,
+    last_seq_num: u64,
+    records: u64,
+    bytes: u64,
+}
+
+struct Segment {
+    path: PathBuf,
+    file: BufWriter<File>,
+    first_seq_num: u64,
+    last_seq_num: u64,
+    records: u64,
+    bytes: u64,
+    opened_at: SystemTime,
+    deadline: Option<Instant>,
+}
+
+/// Writer that splits records across segment files in a directory.
+pub struct RotatingWriter {
+    dir: PathBuf,
+    args: RotateArgs,
+    segment: Option<Segment>,
+}
+
+impl RotatingWriter {
+    pub async fn new(dir: PathBuf, args: RotateArgs) -> Result<Self, S2CliError> {
+        tokio::fs::create_dir_all(&dir)
+            .await
+            .map_err(|e| S2CliError::file_write(&dir, e))?;
+        Ok(Self {
+            dir,
+            args,
+            segment: None,
+        })
+    }
+
+    /// When the current segment should be rotated by age, if ever.
+    pub fn deadline(&self) -> Option<Instant> {
+        self.segment.as_ref().and_then(|s| s.deadline)
+    }
+
+    /// Write a batch of rendered records, given as the end offset in `buf` of
+    /// each record along with its sequence number.
+    ///
+    /// Segments are only rotated between records.
+    pub async fn write_batch(
+        &mut self,
+        buf: &[u8],
+        record_ends: &[(u64, usize)],
+    ) -> Result<(), S2CliError> {
+        let mut start = 0;
+        for &(seq_num, end) in record_ends {
+            if self.segment.is_none() {
+                self.segment = Some(self.open_segment(seq_num).await?);
+            }
+            let segment = self.segment.as_mut().expect("open segment");
+            segment
+                .file
+                .write_all(&buf[start..end])
+                .await
+                .map_err(|e| S2CliError::file_write(&segment.path, e))?;
+            segment.last_seq_num = seq_num;
+            segment.records += 1;
+            segment.bytes += (end - start) as u64;
+            start = end;
+
+            let full = self
+                .args
+                .rotate_bytes
+                .is_some_and(|max| segment.bytes >= max)
+                || self
+                    .args
+                    .rotate_records
+                    .is_some_and(|max| segment.records >= max);
+            let expired = segment.deadline.is_some_and(|d| d <= Instant::now());
+            if full || expired {
+                self.rotate().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sequence number of the first record in the current segment, which is
+    /// only complete once the segment is rotated.
+    pub fn pending_seq_num(&self) -> Option<u64> {
+        self.segment.as_ref().map(|s| s.first_seq_num)
+    }
+
+    pub async fn flush(&mut self) -> Result<(), S2CliError> {
+        if let Some(segment) = &mut self.segment {
+            segment
+                .file
+                .flush()
+                .await
+                .map_err(|e| S2CliError::file_write(&segment.path, e))?;
+        }
+        Ok(())
+    }
+
+    async fn open_segment(&self, first_seq_num: u64) -> Result<Segment, S2CliError> {
+        let path = self.dir.join(format!(".{first_seq_num}.partial"));
+        let file = File::create(&path)
+            .await
+            .map_err(|e| S2CliError::file_write(&path, e))?;
+        Ok(Segment {
+            path,
+            file: BufWriter::new(file),
+            first_seq_num,
+            last_seq_num: first_seq_num,
+            records: 0,
+            bytes: 0,
+            opened_at: SystemTime::now(),
+            deadline: self
+                .args
+                .rotate_interval
+                .map(|interval| Instant::now() + *interval),
+        })
+    }
+
+    /// Close the current segment, if any, so the next record starts a new one.
+    pub async fn rotate(&mut self) -> Result<(), S2CliError> {
+        let Some(mut segment) = self.segment.take() else {
+            return Ok(());
+        };
+        segment
+            .file
+            .flush()
+            .await
+            .map_err(|e| S2CliError::file_write(&segment.path, e))?;
+        drop(segment.file);
+
+        let mut name = self.args.rotate_name.render(
+            segment.first_seq_num,
+            segment.last_seq_num,
+            segment.opened_at,
+        );
+        let mut path = self.dir.join(&name);
+        tokio::fs::rename(&segment.path, &path)
+            .await
+            .map_err(|e| S2CliError::file_write(&path, e))?;
+
+        if self.args.rotate_gzip {
+            name.push_str(".gz");
+            let gz_path = self.dir.join(&name);
+            let (src, dst) = (path.clone(), gz_path.clone());
+            tokio::task::spawn_blocking(move || gzip_file(&src, &dst))
+                .await
+                .expect("gzip task")
+                .map_err(|e| S2CliError::file_write(&gz_path, e))?;
+            tokio::fs::remove_file(&path)
+                .await
+                .map_err(|e| S2CliError::file_write(&path, e))?;
+            path = gz_path;
+        }
+
+        let mut line = serde_json::to_vec(&IndexEntry {
+            file: &name,
+            first_seq_num: segment.first_seq_num,
+            last_seq_num: segment.last_seq_num,
+            records: segment.records,
+            bytes: segment.bytes,
+        })
+        .expect("serializable index entry");
+        line.push(b'\n');
+
+        let index_path = self.dir.join(INDEX_FILE_NAME);
+        let mut index = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&index_path)
+            .await
+            .map_err(|e| S2CliError::file_write(&index_path, e))?;
+        // Tokio files write in the background, so flush to make sure the
+        // entry lands before the file is dropped.
+        index
+            .write_all(&line)
+            .await
+            .map_err(|e| S2CliError::file_write(&index_path, e))?;
+        index
+            .flush()
+            .await
+            .map_err(|e| S2CliError::file_write(&index_path, e))?;
+
+        eprintln!("Rotated output to {}", path.display());
+        Ok(())
+    }
+}
+
+fn gzip_file(src: &Path, dst: &Path) -> std::io::Result<()> {
+    let mut input = std::fs::File::open(src)?;
+    let mut encoder =
+        flate2::write::GzEncoder::new(std::fs::File::create(dst)?, flate2::Compression::default());
+    std::io::copy(&mut input, &mut encoder)?;
+    encoder.finish()?.sync_all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempDir;
+
+    #[test]
+    fn test_segment_name() {
+        let name: SegmentName = "orders-{first_seq_num}-{last_seq_num}-{timestamp}.jsonl"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            name.render(
+                10,
+                19,
+                SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(86400)
+            ),
+            "orders-10-19-19700102T000000Z.jsonl"
+        );
+
+        assert!("{first}.out".parse::<SegmentName>().is_err());
+        assert!("{first_seq_num.out".parse::<SegmentName>().is_err());
+        assert!("dir/{first_seq_num}.out".parse::<SegmentName>().is_err());
+        assert!("out.log".parse::<SegmentName>().is_err());
+        assert!("{timestamp}.out".parse::<SegmentName>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rotating_writer() {
+        let dir = TempDir::new("rotate");
+        let args = RotateArgs {
+            rotate_bytes: None,
+            rotate_records: Some(2),
+            rotate_interval: None,
+            rotate_name: "{first_seq_num}-{last_seq_num}.out".parse().unwrap(),
+            rotate_gzip: false,
+        };
+        let mut writer = RotatingWriter::new(dir.path().to_path_buf(), args)
+            .await
+            .unwrap();
+        writer
+            .write_batch(b"a\nb\nc\n", &[(0, 2), (1, 4), (2, 6)])
+            .await
+            .unwrap();
+        assert_eq!(writer.pending_seq_num(), Some(2));
+        writer.rotate().await.unwrap();
+        assert_eq!(writer.pending_seq_num(), None);
+
+        assert_eq!(std::fs::read(dir.join("0-1.out")).unwrap(), b"a\nb\n");
+        assert_eq!(std::fs::read(dir.join("2-2.out")).unwrap(), b"c\n");
+        assert_eq!(
+            std::fs::read_to_string(dir.join(INDEX_FILE_NAME)).unwrap(),
+            "{\"file\":\"0-1.out\",\"first_seq_num\":0,\"last_seq_num\":1,\"records\":2,\"bytes\":4}\n\
+             {\"file\":\"2-2.out\",\"first_seq_num\":2,\"last_seq_num\":2,\"records\":1,\"bytes\":2}\n"
+        );
+    }
+}
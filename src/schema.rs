@@ -0,0 +1,213 @@
+//! JSON Schema validation of record bodies before they are appended.
+
+use std::{io::Write, path::Path};
+
+use clap::ValueEnum;
+use futures::{Stream, StreamExt};
+use miette::{Diagnostic, LabeledSpan, NamedSource, SourceSpan};
+use s2::types::AppendRecord;
+use thiserror::Error;
+use tokio::sync::oneshot;
+
+use crate::error::S2CliError;
+
+/// What to do with records that do not match the schema.
+#[derive(ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OnInvalid {
+    /// Stop appending at the first invalid record and fail.
+    Reject,
+    /// Report invalid records and continue with the next one.
+    Skip,
+}
+
+#[derive(Debug, Error, Diagnostic)]
+#[error("Record {record} does not match the schema")]
+#[diagnostic(help("{help}"))]
+pub struct SchemaViolation {
+    record: usize,
+    help: String,
+    #[source_code]
+    body: NamedSource<String>,
+    #[label(collection)]
+    labels: Vec<LabeledSpan>,
+}
+
+pub struct SchemaValidator {
+    validator: jsonschema::Validator,
+    source_name: String,
+}
+
+impl SchemaValidator {
+    /// Load and compile the JSON schema at `path`.
+    ///
+    /// `source_name` identifies the input in diagnostics.
+    pub fn load(path: &Path, source_name: String) -> Result<Self, S2CliError> {
+        let validator = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()))
+            .and_then(|schema| jsonschema::validator_for(&schema).map_err(|e| e.to_string()))
+            .map_err(|e| S2CliError::InvalidSchema(miette::miette!("{}: {e}", path.display())))?;
+
+        Ok(Self {
+            validator,
+            source_name,
+        })
+    }
+
+    /// Validate the body of the `record`-th record (starting at 1).
+    pub fn validate(&self, record: usize, body: &[u8]) -> Result<(), Box<SchemaViolation>> {
+        let text = String::from_utf8_lossy(body);
+
+        let (help, labels) = match serde_json::from_slice::<serde_json::Value>(body) {
+            Ok(instance) => {
+                let labels: Vec<_> = self
+                    .validator
+                    .iter_errors(&instance)
+                    .map(|e| {
+                        let pointer = e.instance_path.as_str();
+                        let pointer = if pointer.is_empty() { "/" } else { pointer };
+                        LabeledSpan::new_with_span(
+                            Some(format!("{pointer}: {e}")),
+                            locate_pointer(&text, e.instance_path.as_str()),
+                        )
+                    })
+                    .collect();
+                if labels.is_empty() {
+                    return Ok(());
+                }
+                (
+                    "Fix the labelled values to match the schema".to_string(),
+                    labels,
+                )
+            }
+            Err(e) => {
+                let offset = text
+                    .split_inclusive('\n')
+                    .take(e.line().saturating_sub(1))
+                    .map(str::len)
+                    .sum::<usize>()
+                    + e.column().saturating_sub(1);
+                let label = LabeledSpan::new_with_span(
+                    Some(e.to_string()),
+                    SourceSpan::from((offset.min(text.len()), 0)),
+                );
+                ("Record body must be valid JSON".to_string(), vec![label])
+            }
+        };
+
+        Err(Box::new(SchemaViolation {
+            record,
+            help,
+            body: NamedSource::new(&self.source_name, text.into_owned()),
+            labels,
+        }))
+    }
+}
+
+/// Best effort span for the value at a JSON pointer, found by searching for the
+/// last object key in the pointer. Falls back to the whole text.
+fn locate_pointer(text: &str, pointer: &str) -> SourceSpan {
+    let whole = SourceSpan::from((0, text.len()));
+    let Some(key) = pointer.rsplit('/').next().filter(|k| !k.is_empty()) else {
+        return whole;
+    };
+    let key = key.replace("~1", "/").replace("~0", "~");
+    if key.parse::<usize>().is_ok() {
+        // Array index, which can't be located by searching.
+        return whole;
+    }
+
+    let needle = format!("\"{key}\"");
+    text.match_indices(&needle)
+        .find(|(idx, _)| text[idx + needle.len()..].trim_start().starts_with(':'))
+        .map_or(whole, |(idx, _)| SourceSpan::from((idx, needle.len())))
+}
+
+/// Validate each record against the schema before it is appended.
+///
+/// Invalid records are written to `dead_letter` if provided, one per line. With
+/// [`OnInvalid::Reject`], the stream ends at the first invalid record, which is
+/// reported through `rejected`. With [`OnInvalid::Skip`], violations are
+/// reported to stderr and the record is dropped.
+pub fn validate_records(
+    mut records: impl Stream<Item = AppendRecord> + Send + Unpin + 'static,
+    validator: SchemaValidator,
+    on_invalid: OnInvalid,
+    mut dead_letter: Option<std::fs::File>,
+    rejected: oneshot::Sender<Box<SchemaViolation>>,
+) -> impl Stream<Item = AppendRecord> + Send + 'static {
+    async_stream::stream! {
+        let mut rejected = Some(rejected);
+        let mut record_num = 0;
+        while let Some(record) = records.next().await {
+            record_num += 1;
+            let Err(violation) = validator.validate(record_num, record.body()) else {
+                yield record;
+                continue;
+            };
+
+            if let Some(file) = dead_letter.as_mut() {
+                if let Err(e) = file
+                    .write_all(record.body())
+                    .and_then(|_| file.write_all(b"\n"))
+                {
+                    eprintln!("Error writing to dead letter file: {e}");
+                }
+            }
+
+            match on_invalid {
+                OnInvalid::Reject => {
+                    if let Some(tx) = rejected.take() {
+                        let _ = tx.send(violation);
+                    }
+                    break;
+                }
+                OnInvalid::Skip => eprintln!("{:?}", miette::Report::new(*violation)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validator() -> SchemaValidator {
+        let schema = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "user": {
+                    "type": "object",
+                    "properties": { "id": { "type": "integer" } }
+                },
+                "event": { "type": "string" }
+            },
+            "required": ["event"]
+        });
+        SchemaValidator {
+            validator: jsonschema::validator_for(&schema).unwrap(),
+            source_name: "stdin".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        let validator = validator();
+        assert!(validator
+            .validate(1, br#"{"event": "login", "user": {"id": 1}}"#)
+            .is_ok());
+
+        let violation = validator
+            .validate(2, br#"{"event": "login", "user": {"id": "x"}}"#)
+            .unwrap_err();
+        assert_eq!(violation.record, 2);
+        assert_eq!(violation.labels.len(), 1);
+        assert_eq!(violation.labels[0].offset(), 28);
+
+        let violation = validator.validate(3, br#"{"user": {}}"#).unwrap_err();
+        assert_eq!(violation.labels[0].offset(), 0);
+
+        let violation = validator.validate(4, b"{not json").unwrap_err();
+        assert_eq!(violation.labels[0].offset(), 1);
+    }
+}
@@ -0,0 +1,519 @@
+//! Outputs of a read session.
+//!
+//! A read session can write to several sinks at once, each rendering records in
+//! its own format. Batches are written to all sinks concurrently, and the next
+//! batch is only read once every sink has taken the previous one, so a slow
+//! sink holds back the session rather than buffering without bound.
+
+use std::{io::IsTerminal, path::PathBuf, str::FromStr};
+
+use clap::ValueEnum;
+use futures::future::try_join_all;
+use s2::types::{CommandRecord, SequencedRecord};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    time::Instant,
+};
+
+use crate::{
+    columnar::{ColumnarArgs, ColumnarFormat, ColumnarWriter},
+    error::S2CliError,
+    exec::{ExecArgs, ExecWriter},
+    formats::{CommandsMode, OutputFormat, RecordFormatter},
+    parse_records_output_source,
+    rotate::{RotateArgs, RotatingWriter},
+    sqlite::SqliteWriter,
+    RecordsOut,
+};
+
+/// A record in a batch, ready to be rendered by each sink.
+pub enum BatchItem {
+    Record(SequencedRecord),
+    Command {
+        seq_num: u64,
+        command: CommandRecord,
+    },
+}
+
+/// An output given as `[FORMAT:]PATH`, where "-" is stdout.
+#[derive(Debug, Clone)]
+pub struct SinkSpec {
+    format: Option<OutputFormat>,
+    output: RecordsOut,
+}
+
+impl SinkSpec {
+    pub fn is_file(&self) -> bool {
+        matches!(self.output, RecordsOut::File(_))
+    }
+}
+
+impl FromStr for SinkSpec {
+    type Err = std::io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((prefix, path)) = s.split_once(':') {
+            if let Ok(format) = OutputFormat::from_str(prefix, true) {
+                return Ok(Self {
+                    format: Some(format),
+                    output: parse_records_output_source(path)?,
+                });
+            }
+        }
+        Ok(Self {
+            format: None,
+            output: parse_records_output_source(s)?,
+        })
+    }
+}
+
+/// An output that stores records as rows of a table, given as `KIND:PATH`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TableSinkSpec {
+    Sqlite(PathBuf),
+    Columnar(ColumnarFormat, PathBuf),
+}
+
+impl TableSinkSpec {
+    /// Whether reading resumes after the records already in the output.
+    pub fn resumes(&self) -> bool {
+        matches!(self, Self::Sqlite(_))
+    }
+
+    /// Whether the output is only complete once the read session ends.
+    pub fn needs_bounded_read(&self) -> bool {
+        matches!(self, Self::Columnar(..))
+    }
+}
+
+impl FromStr for TableSinkSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((kind, path)) if !path.is_empty() => match kind {
+                "sqlite" => Ok(Self::Sqlite(path.into())),
+                "parquet" => Ok(Self::Columnar(ColumnarFormat::Parquet, path.into())),
+                "arrow" => Ok(Self::Columnar(ColumnarFormat::ArrowIpc, path.into())),
+                _ => Err(format!(
+                    "unknown sink '{kind}', expected sqlite, parquet or arrow"
+                )),
+            },
+            _ => Err(format!("expected 'KIND:PATH', got '{s}'")),
+        }
+    }
+}
+
+/// Destination of rendered records in a read session.
+enum RecordsWriter {
+    Plain(Box<dyn AsyncWrite + Send + Unpin>),
+    Rotating(Box<RotatingWriter>),
+    Exec(Box<ExecWriter>),
+}
+
+impl RecordsWriter {
+    async fn new(output: RecordsOut, rotate: &RotateArgs) -> Result<Self, S2CliError> {
+        match output {
+            RecordsOut::File(dir) if rotate.enabled() => Ok(Self::Rotating(Box::new(
+                RotatingWriter::new(dir, rotate.clone()).await?,
+            ))),
+            output => {
+                let writer = output
+                    .into_writer()
+                    .await
+                    .map_err(|e| S2CliError::RecordWrite(e.to_string()))?;
+                Ok(Self::Plain(writer))
+            }
+        }
+    }
+
+    /// Write a batch of rendered records, given as the end offset in `buf` of
+    /// each record along with its sequence number.
+    async fn write_batch(
+        &mut self,
+        buf: &[u8],
+        record_ends: &[(u64, usize)],
+    ) -> Result<(), S2CliError> {
+        match self {
+            Self::Plain(writer) => writer
+                .write_all(buf)
+                .await
+                .map_err(|e| S2CliError::RecordWrite(e.to_string())),
+            Self::Rotating(writer) => writer.write_batch(buf, record_ends).await,
+            Self::Exec(writer) => writer.write_batch(buf, record_ends).await,
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), S2CliError> {
+        match self {
+            Self::Plain(writer) => writer
+                .flush()
+                .await
+                .map_err(|e| S2CliError::RecordWrite(e.to_string())),
+            Self::Rotating(writer) => writer.flush().await,
+            Self::Exec(_) => Ok(()),
+        }
+    }
+
+    fn rotation_deadline(&self) -> Option<Instant> {
+        match self {
+            Self::Rotating(writer) => writer.deadline(),
+            Self::Plain(_) | Self::Exec(_) => None,
+        }
+    }
+
+    async fn rotate(&mut self) -> Result<(), S2CliError> {
+        match self {
+            Self::Rotating(writer) => writer.rotate().await,
+            Self::Plain(_) | Self::Exec(_) => Ok(()),
+        }
+    }
+
+    /// Sequence number of the first record written but not yet consumed.
+    fn pending_seq_num(&self) -> Option<u64> {
+        match self {
+            Self::Rotating(writer) => writer.pending_seq_num(),
+            Self::Exec(writer) => writer.pending_seq_num(),
+            Self::Plain(_) => None,
+        }
+    }
+
+    /// Flush any buffered output, close the current output file if rotating,
+    /// and run the command on any remaining records.
+    async fn close(&mut self) -> Result<(), S2CliError> {
+        match self {
+            Self::Plain(_) => self.flush().await,
+            Self::Rotating(writer) => {
+                writer.flush().await?;
+                writer.rotate().await
+            }
+            Self::Exec(writer) => writer.run().await,
+        }
+    }
+}
+
+/// An output of a read session.
+enum Sink {
+    /// Records rendered in a format of their own.
+    Rendered {
+        formatter: RecordFormatter,
+        commands: CommandsMode,
+        writer: RecordsWriter,
+    },
+    /// Records stored as rows of a table. Command records are not stored.
+    Sqlite(Box<SqliteWriter>),
+    Columnar(Box<ColumnarWriter>),
+}
+
+/// The records in a batch, leaving out command records.
+fn records(items: &[BatchItem]) -> impl Iterator<Item = &SequencedRecord> {
+    items.iter().filter_map(|item| match item {
+        BatchItem::Record(record) => Some(record),
+        BatchItem::Command { .. } => None,
+    })
+}
+
+impl Sink {
+    fn rendered(
+        formatter: RecordFormatter,
+        commands: Option<CommandsMode>,
+        writer: RecordsWriter,
+    ) -> Self {
+        Self::Rendered {
+            commands: commands.unwrap_or_else(|| formatter.default_commands_mode()),
+            formatter,
+            writer,
+        }
+    }
+
+    fn render(
+        formatter: &RecordFormatter,
+        commands: CommandsMode,
+        items: &[BatchItem],
+        buf: &mut Vec<u8>,
+        record_ends: &mut Vec<(u64, usize)>,
+    ) {
+        for item in items {
+            let len_before = buf.len();
+            let seq_num = match item {
+                BatchItem::Record(record) => {
+                    formatter.write_record(record, buf);
+                    record.seq_num
+                }
+                BatchItem::Command { seq_num, command } => {
+                    if commands == CommandsMode::Inline {
+                        formatter.write_command(*seq_num, command, buf);
+                    }
+                    *seq_num
+                }
+            };
+            if buf.len() > len_before {
+                record_ends.push((seq_num, buf.len()));
+            }
+        }
+    }
+
+    async fn write_batch(&mut self, items: &[BatchItem]) -> Result<(), S2CliError> {
+        match self {
+            Self::Rendered {
+                formatter,
+                commands,
+                writer,
+            } => {
+                let mut buf = Vec::new();
+                let mut record_ends = Vec::new();
+                Self::render(formatter, *commands, items, &mut buf, &mut record_ends);
+                writer.write_batch(&buf, &record_ends).await
+            }
+            Self::Sqlite(writer) => writer.write_batch(records(items)).await,
+            Self::Columnar(writer) => writer.write_batch(records(items)).await,
+        }
+    }
+
+    fn writer(&self) -> Option<&RecordsWriter> {
+        match self {
+            Self::Rendered { writer, .. } => Some(writer),
+            Self::Sqlite(_) | Self::Columnar(_) => None,
+        }
+    }
+
+    fn writer_mut(&mut self) -> Option<&mut RecordsWriter> {
+        match self {
+            Self::Rendered { writer, .. } => Some(writer),
+            Self::Sqlite(_) | Self::Columnar(_) => None,
+        }
+    }
+
+    /// Sequence number of the first record written but not yet consumed.
+    fn pending_seq_num(&self) -> Option<u64> {
+        match self {
+            Self::Rendered { writer, .. } => writer.pending_seq_num(),
+            Self::Sqlite(_) => None,
+            Self::Columnar(writer) => writer.pending_seq_num(),
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), S2CliError> {
+        match self {
+            Self::Rendered { writer, .. } => writer.close().await,
+            Self::Sqlite(_) => Ok(()),
+            Self::Columnar(writer) => writer.close().await,
+        }
+    }
+
+    async fn abort(&mut self) -> Result<(), S2CliError> {
+        match self {
+            Self::Columnar(writer) => {
+                writer.abandon();
+                Ok(())
+            }
+            _ => self.close().await,
+        }
+    }
+}
+
+/// All outputs of a read session.
+pub struct Sinks {
+    sinks: Vec<Sink>,
+}
+
+impl Sinks {
+    /// Open the outputs, along with the exec consumer if configured. Outputs
+    /// without a format of their own render records with `formatter`.
+    ///
+    /// Records go to stdout if there are no outputs and no exec consumer.
+    pub async fn open(
+        mut specs: Vec<SinkSpec>,
+        tables: Vec<TableSinkSpec>,
+        formatter: &RecordFormatter,
+        commands: Option<CommandsMode>,
+        rotate: &RotateArgs,
+        exec: &ExecArgs,
+        columnar: &ColumnarArgs,
+    ) -> Result<Self, S2CliError> {
+        let mut sinks = Vec::with_capacity(specs.len() + tables.len() + 1);
+        if let Some(writer) = ExecWriter::new(exec) {
+            sinks.push(Sink::rendered(
+                formatter.for_terminal(false),
+                commands,
+                RecordsWriter::Exec(Box::new(writer)),
+            ));
+        } else if specs.is_empty() && tables.is_empty() {
+            specs.push(SinkSpec {
+                format: None,
+                output: RecordsOut::Stdout,
+            });
+        }
+        for spec in specs {
+            let formatter = match spec.format {
+                Some(format) => formatter.for_format(format),
+                None => formatter.clone(),
+            }
+            .for_terminal(
+                matches!(spec.output, RecordsOut::Stdout) && std::io::stdout().is_terminal(),
+            );
+            sinks.push(Sink::rendered(
+                formatter,
+                commands,
+                RecordsWriter::new(spec.output, rotate).await?,
+            ));
+        }
+
+        for table in tables {
+            match table {
+                TableSinkSpec::Sqlite(path) => {
+                    let writer = SqliteWriter::open(path)?;
+                    sinks.push(Sink::Sqlite(Box::new(writer)));
+                }
+                TableSinkSpec::Columnar(format, path) => {
+                    let writer = ColumnarWriter::open(format, path, columnar)?;
+                    sinks.push(Sink::Columnar(Box::new(writer)));
+                }
+            }
+        }
+        Ok(Self { sinks })
+    }
+
+    /// Sequence number to resume reading from, following the records already
+    /// stored by the table outputs, without opening them.
+    pub fn resume_seq_num(tables: &[TableSinkSpec]) -> Result<Option<u64>, S2CliError> {
+        // A table without records needs reading from the start, which
+        // `None` sorting first takes care of.
+        let mut table_positions = Vec::with_capacity(tables.len());
+        for table in tables {
+            if let TableSinkSpec::Sqlite(path) = table {
+                table_positions.push(SqliteWriter::next_seq_num(path)?);
+            }
+        }
+        Ok(table_positions.into_iter().min().flatten())
+    }
+
+    /// Whether command records should be described on stderr.
+    pub fn reports_commands(&self) -> bool {
+        self.sinks.iter().any(|sink| {
+            matches!(
+                sink,
+                Sink::Rendered {
+                    commands: CommandsMode::Stderr,
+                    ..
+                }
+            )
+        })
+    }
+
+    pub async fn write_batch(&mut self, items: &[BatchItem]) -> Result<(), S2CliError> {
+        try_join_all(self.sinks.iter_mut().map(|sink| sink.write_batch(items))).await?;
+        Ok(())
+    }
+
+    pub async fn flush(&mut self) -> Result<(), S2CliError> {
+        try_join_all(
+            self.sinks
+                .iter_mut()
+                .filter_map(Sink::writer_mut)
+                .map(RecordsWriter::flush),
+        )
+        .await?;
+        Ok(())
+    }
+
+    /// Wait until an output file should be rotated by age. Never completes if
+    /// there is no such deadline.
+    pub async fn rotation_due(deadline: Option<Instant>) {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// The earliest time an output file should be rotated by age.
+    pub fn rotation_deadline(&self) -> Option<Instant> {
+        self.sinks
+            .iter()
+            .filter_map(Sink::writer)
+            .filter_map(RecordsWriter::rotation_deadline)
+            .min()
+    }
+
+    /// Rotate the output files that are due.
+    pub async fn rotate_due(&mut self) -> Result<(), S2CliError> {
+        let now = Instant::now();
+        for writer in self.sinks.iter_mut().filter_map(Sink::writer_mut) {
+            if writer.rotation_deadline().is_some_and(|d| d <= now) {
+                writer.rotate().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sequence number up to which all sinks have consumed records, given the
+    /// sequence number following the records written so far.
+    pub fn committed_seq_num(&self, next_seq_num: u64) -> u64 {
+        self.sinks
+            .iter()
+            .filter_map(Sink::pending_seq_num)
+            .fold(next_seq_num, u64::min)
+    }
+
+    /// Flush any buffered output, close the current output files if rotating,
+    /// run the exec command on any remaining records, and finish columnar
+    /// files.
+    pub async fn close(&mut self) -> Result<(), S2CliError> {
+        for sink in &mut self.sinks {
+            sink.close().await?;
+        }
+        Ok(())
+    }
+
+    /// Close the outputs of a read that was aborted. Columnar files are left
+    /// at their `.partial` path rather than finished, as they wouldn't hold
+    /// the whole range.
+    pub async fn abort(&mut self) -> Result<(), S2CliError> {
+        for sink in &mut self.sinks {
+            sink.abort().await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sink_spec() {
+        let spec: SinkSpec = "json:archive.jsonl".parse().unwrap();
+        assert_eq!(spec.format, Some(OutputFormat::Json));
+        assert!(
+            matches!(spec.output, RecordsOut::File(path) if path.to_str() == Some("archive.jsonl"))
+        );
+
+        let spec: SinkSpec = "text:-".parse().unwrap();
+        assert_eq!(spec.format, Some(OutputFormat::Text));
+        assert!(matches!(spec.output, RecordsOut::Stdout));
+
+        let spec: SinkSpec = "logs:2024.txt".parse().unwrap();
+        assert_eq!(spec.format, None);
+        assert!(
+            matches!(spec.output, RecordsOut::File(path) if path.to_str() == Some("logs:2024.txt"))
+        );
+    }
+
+    #[test]
+    fn test_table_sink_spec() {
+        assert_eq!(
+            "sqlite:records.db".parse(),
+            Ok(TableSinkSpec::Sqlite("records.db".into()))
+        );
+        assert_eq!(
+            "parquet:out/records.parquet".parse(),
+            Ok(TableSinkSpec::Columnar(
+                ColumnarFormat::Parquet,
+                "out/records.parquet".into()
+            ))
+        );
+        assert!("sqlite:".parse::<TableSinkSpec>().is_err());
+        assert!("csv:records.csv".parse::<TableSinkSpec>().is_err());
+        assert!("records.db".parse::<TableSinkSpec>().is_err());
+    }
+}
@@ -0,0 +1,198 @@
+//! SQLite export of records in a read session.
+//!
+//! Records are stored as rows of a `records` table, with each batch inserted
+//! in a single transaction. Rows are keyed by sequence number, so records read
+//! again after a restart replace the rows already stored for them.
+
+use std::path::{Path, PathBuf};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
+use rusqlite::{types::Value, Connection, OpenFlags};
+use s2::types::SequencedRecord;
+
+use crate::error::S2CliError;
+
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS records (
+    seq_num INTEGER PRIMARY KEY,
+    headers TEXT NOT NULL,
+    headers_base64 INTEGER NOT NULL,
+    body BLOB NOT NULL
+)";
+
+/// A record as a row of the `records` table.
+struct Row {
+    seq_num: i64,
+    headers: String,
+    headers_base64: bool,
+    body: Value,
+}
+
+impl From<&SequencedRecord> for Row {
+    fn from(record: &SequencedRecord) -> Self {
+        let headers_base64 = record.headers.iter().any(|h| {
+            std::str::from_utf8(&h.name).is_err() || std::str::from_utf8(&h.value).is_err()
+        });
+        let encode = |bytes: &[u8]| {
+            if headers_base64 {
+                BASE64.encode(bytes)
+            } else {
+                String::from_utf8_lossy(bytes).into_owned()
+            }
+        };
+        let headers: Vec<_> = record
+            .headers
+            .iter()
+            .map(|h| (encode(&h.name), encode(&h.value)))
+            .collect();
+        Self {
+            seq_num: record.seq_num as i64,
+            headers: serde_json::to_string(&headers).expect("serializable headers"),
+            headers_base64,
+            body: match std::str::from_utf8(&record.body) {
+                Ok(body) => Value::Text(body.to_string()),
+                Err(_) => Value::Blob(record.body.to_vec()),
+            },
+        }
+    }
+}
+
+/// Writer that inserts records into a SQLite database.
+///
+/// Headers are stored as a JSON array of name and value pairs. If any name or
+/// value isn't valid UTF-8, all of them are base64 encoded and the row's
+/// `headers_base64` flag is set. Bodies are stored as text if they are valid
+/// UTF-8, and as blobs otherwise.
+pub struct SqliteWriter {
+    path: PathBuf,
+    // Taken while a batch is inserted on the blocking thread pool.
+    conn: Option<Connection>,
+}
+
+impl SqliteWriter {
+    /// Open the database at `path`, creating it and the `records` table if
+    /// needed.
+    pub fn open(path: PathBuf) -> Result<Self, S2CliError> {
+        let conn = Connection::open(&path).map_err(|e| S2CliError::file_write(&path, e))?;
+        conn.execute(CREATE_TABLE, [])
+            .map_err(|e| S2CliError::file_write(&path, e))?;
+        Ok(Self {
+            path,
+            conn: Some(conn),
+        })
+    }
+
+    /// The sequence number following the last record stored in the database
+    /// at `path`, if it exists and holds any records.
+    pub fn next_seq_num(path: &Path) -> Result<Option<u64>, S2CliError> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| S2CliError::file_write(path, e))?;
+        let has_table: bool = conn
+            .query_row(
+                "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'records')",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|e| S2CliError::file_write(path, e))?;
+        if !has_table {
+            return Ok(None);
+        }
+        let max_seq_num: Option<i64> = conn
+            .query_row("SELECT MAX(seq_num) FROM records", [], |row| row.get(0))
+            .map_err(|e| S2CliError::file_write(path, e))?;
+        Ok(max_seq_num.map(|seq_num| seq_num as u64 + 1))
+    }
+
+    /// Insert a batch of records in one transaction.
+    pub async fn write_batch<'a>(
+        &mut self,
+        records: impl IntoIterator<Item = &'a SequencedRecord>,
+    ) -> Result<(), S2CliError> {
+        let rows: Vec<Row> = records.into_iter().map(Row::from).collect();
+        if rows.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.conn.take().expect("open connection");
+        let (conn, result) = tokio::task::spawn_blocking(move || {
+            let result = insert_rows(&mut conn, rows);
+            (conn, result)
+        })
+        .await
+        .expect("sqlite insert task");
+        self.conn = Some(conn);
+        result.map_err(|e| S2CliError::file_write(&self.path, e))
+    }
+}
+
+fn insert_rows(conn: &mut Connection, rows: Vec<Row>) -> rusqlite::Result<()> {
+    let tx = conn.transaction()?;
+    {
+        let mut insert = tx.prepare_cached(
+            "INSERT OR REPLACE INTO records (seq_num, headers, headers_base64, body) \
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for row in rows {
+            insert.execute((row.seq_num, row.headers, row.headers_base64, row.body))?;
+        }
+    }
+    tx.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use s2::types::Header;
+
+    use super::*;
+    use crate::test_util::{record, TempDir};
+
+    #[tokio::test]
+    async fn test_sqlite_writer_resume() {
+        let dir = TempDir::new("sqlite");
+        let path = dir.join("records.db");
+
+        assert_eq!(SqliteWriter::next_seq_num(&path).unwrap(), None);
+        let mut writer = SqliteWriter::open(path.clone()).unwrap();
+        assert_eq!(SqliteWriter::next_seq_num(&path).unwrap(), None);
+        let binary_headers = record(5, vec![Header::new("host", b"\xff".as_slice())], b"");
+        writer
+            .write_batch(&[
+                record(3, vec![Header::new("host", "a")], b"hello"),
+                record(4, vec![Header::new("host", "a")], b"\xff"),
+                binary_headers,
+            ])
+            .await
+            .unwrap();
+        drop(writer);
+
+        assert_eq!(SqliteWriter::next_seq_num(&path).unwrap(), Some(6));
+
+        let conn = Connection::open(&path).unwrap();
+        let (headers, headers_base64, body): (String, bool, Value) = conn
+            .query_row(
+                "SELECT headers, headers_base64, body FROM records WHERE seq_num = 3",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(headers, "[[\"host\",\"a\"]]");
+        assert!(!headers_base64);
+        assert_eq!(body, Value::Text("hello".to_string()));
+        let body: Value = conn
+            .query_row("SELECT body FROM records WHERE seq_num = 4", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(body, Value::Blob(vec![0xff]));
+        let (headers, headers_base64): (String, bool) = conn
+            .query_row(
+                "SELECT headers, headers_base64 FROM records WHERE seq_num = 5",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(headers, "[[\"aG9zdA==\",\"/w==\"]]");
+        assert!(headers_base64);
+    }
+}
@@ -0,0 +1,164 @@
+//! Throughput statistics for read sessions, reported on stderr.
+
+use std::ops::RangeInclusive;
+
+use clap::ValueEnum;
+use colored::*;
+use serde::Serialize;
+use tokio::time::Instant;
+
+use crate::filter::RecordFilter;
+
+#[derive(ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StatsFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// JSON Lines, with one object per report.
+    Json,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum ReportKind {
+    Batch,
+    Progress,
+    Summary,
+}
+
+#[derive(Serialize)]
+struct Report {
+    kind: ReportKind,
+    records: u64,
+    metered_bytes: u64,
+    elapsed_secs: f64,
+    mibps: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    first_seq_num: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_seq_num: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matched: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scanned: Option<u64>,
+}
+
+/// Counts of records and metered bytes read, since the first batch arrived.
+pub struct ReadStats {
+    format: StatsFormat,
+    start: Option<Instant>,
+    records: u64,
+    metered_bytes: u64,
+}
+
+impl ReadStats {
+    pub fn new(format: StatsFormat) -> Self {
+        Self {
+            format,
+            start: None,
+            records: 0,
+            metered_bytes: 0,
+        }
+    }
+
+    pub fn record_batch(&mut self, records: usize, metered_bytes: u64) {
+        self.start.get_or_insert_with(Instant::now);
+        self.records += records as u64;
+        self.metered_bytes += metered_bytes;
+    }
+
+    fn elapsed_secs(&self) -> f64 {
+        self.start
+            .map_or(0.0, |start| start.elapsed().as_secs_f64())
+    }
+
+    fn mibps(&self) -> f64 {
+        let elapsed_secs = self.elapsed_secs();
+        if elapsed_secs > 0.0 {
+            self.metered_bytes as f64 / elapsed_secs / 1024.0 / 1024.0
+        } else {
+            0.0
+        }
+    }
+
+    fn report(&self, kind: ReportKind, filter: &RecordFilter) -> Report {
+        let (matched, scanned) = if filter.is_empty() {
+            (None, None)
+        } else {
+            (Some(filter.matched()), Some(filter.scanned()))
+        };
+        Report {
+            kind,
+            records: self.records,
+            metered_bytes: self.metered_bytes,
+            elapsed_secs: self.elapsed_secs(),
+            mibps: self.mibps(),
+            first_seq_num: None,
+            last_seq_num: None,
+            matched,
+            scanned,
+        }
+    }
+
+    /// Report a batch that was just read, along with the throughput so far.
+    pub fn print_batch(&self, records: usize, metered_bytes: u64, seq_range: &RangeInclusive<u64>) {
+        let mibps = self.mibps();
+        match self.format {
+            StatsFormat::Text => eprintln!(
+                "{}",
+                format!("⦿ {mibps:.2} MiB/s ({records} records in range {seq_range:?})")
+                    .blue()
+                    .bold()
+            ),
+            StatsFormat::Json => print_json(&Report {
+                kind: ReportKind::Batch,
+                records: records as u64,
+                metered_bytes,
+                elapsed_secs: self.elapsed_secs(),
+                mibps,
+                first_seq_num: Some(*seq_range.start()),
+                last_seq_num: Some(*seq_range.end()),
+                matched: None,
+                scanned: None,
+            }),
+        }
+    }
+
+    /// Report cumulative progress so far.
+    pub fn print_progress(&self, filter: &RecordFilter) {
+        let report = self.report(ReportKind::Progress, filter);
+        match self.format {
+            StatsFormat::Text => {
+                eprintln!("{}", format!("⦿ {}", report_text(&report)).blue().bold())
+            }
+            StatsFormat::Json => print_json(&report),
+        }
+    }
+
+    /// Report totals for the whole session.
+    pub fn print_summary(&self, filter: &RecordFilter) {
+        let report = self.report(ReportKind::Summary, filter);
+        match self.format {
+            StatsFormat::Text => eprintln!("{}", report_text(&report).yellow().bold()),
+            StatsFormat::Json => print_json(&report),
+        }
+    }
+}
+
+fn report_text(report: &Report) -> String {
+    let matched = match (report.matched, report.scanned) {
+        (Some(matched), Some(scanned)) => format!(" ({matched} of {scanned} records emitted)"),
+        _ => String::new(),
+    };
+    format!(
+        "{} records, {} metered bytes in {:.2} seconds at {:.2} MiB/s{matched}",
+        report.records, report.metered_bytes, report.elapsed_secs, report.mibps
+    )
+}
+
+fn print_json(report: &Report) {
+    eprintln!(
+        "{}",
+        serde_json::to_string(report).expect("serializable stats")
+    );
+}
@@ -3,18 +3,86 @@ use s2::{
     client::StreamClient,
     types::{
         AppendInput, AppendOutput, AppendRecordBatch, CommandRecord, FencingToken, ReadLimit,
-        ReadOutput, ReadSessionRequest,
+        ReadOutput, ReadRequest, ReadSessionRequest,
     },
     Streaming,
 };
 
+use bytes::{Bytes, BytesMut};
 use futures::{Stream, StreamExt};
 use s2::types::AppendRecord;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use tokio::sync::oneshot;
+use tokio_util::codec::Decoder;
 
 use crate::error::{ServiceError, ServiceErrorContext};
 
+/// Splits input into newline delimited records.
+///
+/// Records are split off the read buffer without copying, and are not
+/// required to be valid UTF-8. A trailing `\r` is stripped from each record.
+#[derive(Debug, Default)]
+pub struct NewlineDelimitedCodec {
+    /// Offset in the buffer up to which there is no newline.
+    next_index: usize,
+}
+
+impl NewlineDelimitedCodec {
+    /// Reserve this much buffer space at a time, so the underlying reader can
+    /// fill large chunks.
+    const READ_CHUNK_BYTES: usize = 1024 * 1024;
+
+    /// A record can never exceed the maximum size of an append record.
+    const MAX_RECORD_BYTES: usize = 1024 * 1024;
+
+    fn split_record(buf: &mut BytesMut, len: usize, delimiter_len: usize) -> Bytes {
+        let mut record = buf.split_to(len + delimiter_len);
+        record.truncate(len);
+        if record.last() == Some(&b'\r') {
+            record.truncate(len - 1);
+        }
+        record.freeze()
+    }
+}
+
+impl Decoder for NewlineDelimitedCodec {
+    type Item = Bytes;
+    type Error = std::io::Error;
+
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, Self::Error> {
+        match memchr::memchr(b'\n', &buf[self.next_index..]) {
+            Some(offset) => {
+                let len = self.next_index + offset;
+                self.next_index = 0;
+                Ok(Some(Self::split_record(buf, len, 1)))
+            }
+            None if buf.len() > Self::MAX_RECORD_BYTES => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "record exceeds 1 MiB",
+            )),
+            None => {
+                self.next_index = buf.len();
+                if buf.capacity() - buf.len() < Self::READ_CHUNK_BYTES / 2 {
+                    buf.reserve(Self::READ_CHUNK_BYTES);
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, buf: &mut BytesMut) -> Result<Option<Bytes>, Self::Error> {
+        match self.decode(buf)? {
+            Some(record) => Ok(Some(record)),
+            None if buf.is_empty() => Ok(None),
+            None => {
+                self.next_index = 0;
+                Ok(Some(Self::split_record(buf, buf.len(), 0)))
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct RecordStream<S> {
     inner: S,
@@ -26,28 +94,39 @@ impl<S> RecordStream<S> {
     }
 }
 
-impl<S: Unpin + Stream<Item = std::io::Result<String>>> Stream for RecordStream<S> {
-    type Item = AppendRecord;
+impl<S: Unpin + Stream<Item = std::io::Result<Bytes>>> Stream for RecordStream<S> {
+    type Item = Result<AppendRecord, String>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         match self.inner.poll_next_unpin(cx) {
-            Poll::Ready(Some(Ok(line))) => match AppendRecord::new(line) {
-                Ok(record) => Poll::Ready(Some(record)),
-                Err(e) => {
-                    eprintln!("Error parsing line: {}", e);
-                    Poll::Ready(None)
-                }
-            },
-            Poll::Ready(Some(Err(e))) => {
-                eprintln!("Error reading line: {}", e);
-                Poll::Ready(None)
+            Poll::Ready(Some(Ok(line))) => {
+                Poll::Ready(Some(AppendRecord::new(line).map_err(|e| e.to_string())))
             }
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e.to_string()))),
             Poll::Ready(None) => Poll::Ready(None),
             Poll::Pending => Poll::Pending,
         }
     }
 }
 
+/// Yield items of a fallible stream up to the first error, which is sent on
+/// `error_tx` so the caller can fail once the stream has been consumed.
+pub fn until_error<T, E>(
+    stream: impl Stream<Item = Result<T, E>>,
+    error_tx: oneshot::Sender<E>,
+) -> impl Stream<Item = T> {
+    let mut error_tx = Some(error_tx);
+    tokio_stream::StreamExt::map_while(stream, move |item| match item {
+        Ok(item) => Some(item),
+        Err(e) => {
+            if let Some(error_tx) = error_tx.take() {
+                let _ = error_tx.send(e);
+            }
+            None
+        }
+    })
+}
+
 pub struct StreamService {
     client: StreamClient,
 }
@@ -99,6 +178,26 @@ impl StreamService {
             .map_err(|e| ServiceError::new(ServiceErrorContext::AppendSession, e))
     }
 
+    /// Read up to `limit_count` records in a single request.
+    pub async fn read(
+        &self,
+        start_seq_num: u64,
+        limit_count: u64,
+    ) -> Result<ReadOutput, ServiceError> {
+        let read_req = ReadRequest {
+            start_seq_num,
+            limit: Some(ReadLimit {
+                count: limit_count,
+                bytes: 0,
+            }),
+        };
+
+        self.client
+            .read(read_req)
+            .await
+            .map_err(|e| ServiceError::new(ServiceErrorContext::Read, e))
+    }
+
     pub async fn read_session(
         &self,
         start_seq_num: u64,
@@ -121,3 +220,50 @@ impl StreamService {
             .map_err(|e| ServiceError::new(ServiceErrorContext::ReadSession, e))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use futures::StreamExt;
+    use tokio::sync::oneshot;
+    use tokio_util::codec::{Decoder, FramedRead};
+
+    use super::{until_error, NewlineDelimitedCodec, RecordStream};
+
+    #[test]
+    fn test_newline_delimited_codec() {
+        let mut codec = NewlineDelimitedCodec::default();
+        let mut buf = BytesMut::from(&b"first\r\nsec"[..]);
+
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), &b"first"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+
+        buf.extend_from_slice(b"ond\n\n\xff\xfe");
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), &b"second"[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap().unwrap(), &b""[..]);
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(
+            codec.decode_eof(&mut buf).unwrap().unwrap(),
+            &b"\xff\xfe"[..]
+        );
+        assert_eq!(codec.decode_eof(&mut buf).unwrap(), None);
+
+        let mut buf = BytesMut::from(&vec![b'x'; 2 * 1024 * 1024][..]);
+        assert!(codec.decode(&mut buf).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_record_stream_read_error() {
+        let mut input = b"first\n".to_vec();
+        input.extend(vec![b'x'; 2 * 1024 * 1024]);
+        let lines = FramedRead::new(input.as_slice(), NewlineDelimitedCodec::default());
+
+        let (error_tx, mut error_rx) = oneshot::channel();
+        let records: Vec<_> = until_error(RecordStream::new(lines), error_tx)
+            .collect()
+            .await;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].body(), b"first");
+        assert!(error_rx.try_recv().is_ok());
+    }
+}
@@ -0,0 +1,436 @@
+//! Following several streams at once, with merged output.
+//!
+//! Each stream is read by its own task, which keeps its own position and
+//! reconnects from it with backoff if its read session fails. Batches are
+//! merged through a channel and every output line is prefixed with a colored
+//! label for its stream, like `kubectl logs` does for multiple pods, or gets
+//! a `stream` field in JSON output.
+//!
+//! Streams under a prefix can also be discovered by listing them again
+//! periodically, following streams as they are created and stopping for ones
+//! that are deleted.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use colored::{Color, Colorize};
+use futures::StreamExt;
+use s2::{
+    client::BasinClient,
+    types::{ReadOutput, SequencedRecord},
+};
+use tokio::{
+    io::{AsyncWrite, AsyncWriteExt},
+    sync::mpsc,
+    task::AbortHandle,
+};
+
+use crate::{
+    backoff,
+    basin::BasinService,
+    error::{S2CliError, ServiceError, ServiceErrorContext},
+    formats::RecordFormatter,
+    stream::StreamService,
+    RECONNECT_BACKOFF_BASE, RECONNECT_BACKOFF_MAX,
+};
+
+/// Colors labels are assigned from, in order.
+const LABEL_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Green,
+    Color::Yellow,
+    Color::Magenta,
+    Color::Blue,
+    Color::Red,
+];
+
+/// Where to start reading a stream from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TailStart {
+    /// This many records before the tail.
+    Last(u64),
+    SeqNum(u64),
+}
+
+pub enum TailEvent {
+    Records {
+        label: Arc<str>,
+        records: Vec<SequencedRecord>,
+    },
+    Error {
+        label: Arc<str>,
+        message: String,
+    },
+}
+
+struct Followed {
+    color: Color,
+    task: AbortHandle,
+}
+
+/// Streams being followed, with the receiving end of their merged output.
+pub struct Tailer {
+    formatter: RecordFormatter,
+    tx: mpsc::Sender<TailEvent>,
+    rx: mpsc::Receiver<TailEvent>,
+    streams: HashMap<Arc<str>, Followed>,
+    colors_used: usize,
+    label_width: usize,
+}
+
+impl Tailer {
+    pub fn new(formatter: RecordFormatter) -> Self {
+        let (tx, rx) = mpsc::channel(64);
+        Self {
+            formatter,
+            tx,
+            rx,
+            streams: HashMap::new(),
+            colors_used: 0,
+            label_width: 0,
+        }
+    }
+
+    pub fn is_following(&self, label: &str) -> bool {
+        self.streams.contains_key(label)
+    }
+
+    /// Start following `stream`, labeling its records with `label`.
+    pub fn follow(
+        &mut self,
+        basin_client: &BasinClient,
+        stream: String,
+        label: String,
+        start: TailStart,
+    ) {
+        let label: Arc<str> = label.into();
+        let stream_service = StreamService::new(basin_client.stream_client(stream));
+        let task = tokio::spawn(follow_stream(
+            stream_service,
+            label.clone(),
+            start,
+            self.tx.clone(),
+        ));
+
+        self.label_width = self.label_width.max(label.chars().count());
+        let color = LABEL_COLORS[self.colors_used % LABEL_COLORS.len()];
+        self.colors_used += 1;
+        self.streams.insert(
+            label,
+            Followed {
+                color,
+                task: task.abort_handle(),
+            },
+        );
+    }
+
+    /// Stop following the stream labeled `label`.
+    pub fn unfollow(&mut self, label: &str) {
+        if let Some(followed) = self.streams.remove(label) {
+            followed.task.abort();
+        }
+    }
+
+    /// The next batch of records or error from any stream.
+    pub async fn recv(&mut self) -> TailEvent {
+        self.rx.recv().await.expect("sender held by tailer")
+    }
+
+    fn label(&self, label: &str) -> String {
+        let color = self
+            .streams
+            .get(label)
+            .map_or(Color::White, |followed| followed.color);
+        format!("{label:<width$} |", width = self.label_width)
+            .color(color)
+            .to_string()
+    }
+
+    /// Write an event, with records to `out` and errors to stderr.
+    pub async fn write(
+        &self,
+        event: TailEvent,
+        out: &mut (impl AsyncWrite + Unpin),
+    ) -> Result<(), S2CliError> {
+        match event {
+            TailEvent::Records { label, records } => {
+                // JSON objects get a stream field rather than a prefix, so the
+                // output stays JSON Lines.
+                let json = self.formatter.writes_json();
+                let prefix = if json {
+                    let mut field = b"{\"stream\":".to_vec();
+                    serde_json::to_writer(&mut field, &*label).expect("serializable label");
+                    field.push(b',');
+                    field
+                } else {
+                    format!("{} ", self.label(&label)).into_bytes()
+                };
+                let mut rendered = Vec::new();
+                let mut buf = Vec::new();
+                for record in &records {
+                    rendered.clear();
+                    match record.as_command_record() {
+                        Some(command) => {
+                            self.formatter
+                                .write_command(record.seq_num, &command, &mut rendered)
+                        }
+                        None => self.formatter.write_record(record, &mut rendered),
+                    }
+                    for line in rendered.split_inclusive(|b| *b == b'\n') {
+                        buf.extend_from_slice(&prefix);
+                        // Objects are never empty, so the opening brace is
+                        // replaced by the stream field.
+                        buf.extend_from_slice(if json { &line[1..] } else { line });
+                    }
+                }
+                out.write_all(&buf)
+                    .await
+                    .map_err(|e| S2CliError::RecordWrite(e.to_string()))?;
+                out.flush()
+                    .await
+                    .map_err(|e| S2CliError::RecordWrite(e.to_string()))
+            }
+            TailEvent::Error { label, message } => {
+                eprintln!("{} {}", self.label(&label), message.yellow().bold());
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for Tailer {
+    fn drop(&mut self) {
+        for followed in self.streams.values() {
+            followed.task.abort();
+        }
+    }
+}
+
+/// Streams followed as they are created and deleted, by listing the streams
+/// with names starting with a prefix.
+pub struct StreamDiscovery {
+    basin_client: BasinClient,
+    basin_service: BasinService,
+    prefix: String,
+}
+
+/// Changes to the streams followed by [`StreamDiscovery::sync`].
+#[derive(Debug, Default)]
+pub struct DiscoveredStreams {
+    pub followed: Vec<String>,
+    pub unfollowed: Vec<String>,
+}
+
+impl StreamDiscovery {
+    pub fn new(basin_client: BasinClient, prefix: String) -> Self {
+        Self {
+            basin_service: BasinService::new(basin_client.clone()),
+            basin_client,
+            prefix,
+        }
+    }
+
+    /// List streams under the prefix, following the ones not yet followed from
+    /// `start`, and no longer following the ones that were deleted. Streams
+    /// are labeled with their names.
+    pub async fn sync(
+        &self,
+        tailer: &mut Tailer,
+        start: TailStart,
+    ) -> Result<DiscoveredStreams, ServiceError> {
+        let names: HashSet<String> = self
+            .basin_service
+            .list_all_streams(&self.prefix)
+            .await?
+            .into_iter()
+            .map(|info| info.name)
+            .collect();
+
+        let mut changes = DiscoveredStreams::default();
+        for name in &names {
+            if !tailer.is_following(name) {
+                tailer.follow(&self.basin_client, name.clone(), name.clone(), start);
+                changes.followed.push(name.clone());
+            }
+        }
+        let deleted: Vec<String> = tailer
+            .streams
+            .keys()
+            .filter(|label| !names.contains(label.as_ref()))
+            .map(|label| label.to_string())
+            .collect();
+        for label in deleted {
+            tailer.unfollow(&label);
+            changes.unfollowed.push(label);
+        }
+        changes.followed.sort();
+        changes.unfollowed.sort();
+        Ok(changes)
+    }
+}
+
+/// Read a stream from `start`, sending its records to `tx` and reconnecting
+/// from the last position on failure, until `tx` is closed.
+async fn follow_stream(
+    stream_service: StreamService,
+    label: Arc<str>,
+    start: TailStart,
+    tx: mpsc::Sender<TailEvent>,
+) {
+    let mut next_seq_num = match start {
+        TailStart::Last(_) => None,
+        TailStart::SeqNum(seq_num) => Some(seq_num),
+    };
+    let mut attempts = 0;
+    loop {
+        let error = match next_seq_num {
+            Some(start_seq_num) => {
+                match read_from(&stream_service, &label, start_seq_num, &tx).await {
+                    Ok(ReadEnd::Closed) => return,
+                    Ok(ReadEnd::Reopen(seq_num)) => {
+                        next_seq_num = Some(seq_num);
+                        continue;
+                    }
+                    Err((seq_num, records_read, error)) => {
+                        next_seq_num = Some(seq_num);
+                        if records_read {
+                            attempts = 0;
+                        }
+                        error
+                    }
+                }
+            }
+            None => match stream_service.check_tail().await {
+                Ok(tail) => {
+                    let TailStart::Last(last) = start else {
+                        unreachable!("position known for other starts")
+                    };
+                    next_seq_num = Some(tail.saturating_sub(last));
+                    continue;
+                }
+                Err(error) => error,
+            },
+        };
+
+        attempts += 1;
+        let backoff = backoff(attempts, RECONNECT_BACKOFF_BASE, RECONNECT_BACKOFF_MAX);
+        let message = format!(
+            "⟳ Reconnecting in {} after {}",
+            humantime::format_duration(backoff),
+            error.to_string().replace('\n', " ")
+        );
+        let event = TailEvent::Error {
+            label: label.clone(),
+            message,
+        };
+        if tx.send(event).await.is_err() {
+            return;
+        }
+        tokio::time::sleep(backoff).await;
+    }
+}
+
+enum ReadEnd {
+    /// Nobody is receiving records any more.
+    Closed,
+    /// The session should be reopened from this position.
+    Reopen(u64),
+}
+
+/// Read from `start_seq_num` until the session ends. Errors come with the
+/// position to resume from, and whether any records were read.
+async fn read_from(
+    stream_service: &StreamService,
+    label: &Arc<str>,
+    start_seq_num: u64,
+    tx: &mpsc::Sender<TailEvent>,
+) -> Result<ReadEnd, (u64, bool, ServiceError)> {
+    let mut next_seq_num = start_seq_num;
+    let mut session = stream_service
+        .read_session(start_seq_num, None, None)
+        .await
+        .map_err(|e| (next_seq_num, false, e))?;
+    while let Some(output) = session.next().await {
+        match output {
+            Ok(ReadOutput::Batch(batch)) => {
+                let Some(last) = batch.records.last() else {
+                    continue;
+                };
+                next_seq_num = last.seq_num + 1;
+                let event = TailEvent::Records {
+                    label: label.clone(),
+                    records: batch.records,
+                };
+                if tx.send(event).await.is_err() {
+                    return Ok(ReadEnd::Closed);
+                }
+            }
+            // The stream was trimmed past the position.
+            Ok(ReadOutput::FirstSeqNum(seq_num)) => return Ok(ReadEnd::Reopen(seq_num)),
+            Ok(ReadOutput::NextSeqNum(_)) => {}
+            Err(e) => {
+                return Err((
+                    next_seq_num,
+                    next_seq_num > start_seq_num,
+                    ServiceError::new(ServiceErrorContext::ReadSession, e),
+                ))
+            }
+        }
+    }
+    Ok(ReadEnd::Reopen(next_seq_num))
+}
+
+#[cfg(test)]
+mod tests {
+    use s2::types::Header;
+
+    use super::*;
+    use crate::{
+        formats::{BodyEncoding, OutputFormat},
+        test_util::record,
+    };
+
+    #[tokio::test]
+    async fn test_labeled_output() {
+        colored::control::set_override(false);
+        let mut tailer = Tailer::new(RecordFormatter::new(OutputFormat::Text, BodyEncoding::Auto));
+        tailer.label_width = "orders".len();
+
+        let mut out = Vec::new();
+        tailer
+            .write(
+                TailEvent::Records {
+                    label: "logs".into(),
+                    records: vec![
+                        record(0, vec![Header::new("host", "a")], "started"),
+                        record(1, vec![], "ready"),
+                    ],
+                },
+                &mut out,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "logs   | started\nlogs   | ready\n"
+        );
+
+        let tailer = Tailer::new(RecordFormatter::new(OutputFormat::Json, BodyEncoding::Auto));
+        let mut out = Vec::new();
+        tailer
+            .write(
+                TailEvent::Records {
+                    label: "logs".into(),
+                    records: vec![record(0, vec![], "started")],
+                },
+                &mut out,
+            )
+            .await
+            .unwrap();
+        let line: serde_json::Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(line["stream"], "logs");
+        assert_eq!(line["body"], "started");
+    }
+}
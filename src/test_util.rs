@@ -0,0 +1,41 @@
+//! Fixtures shared by unit tests.
+
+use std::path::{Path, PathBuf};
+
+use bytes::Bytes;
+use s2::types::{Header, SequencedRecord};
+
+/// A record read from a stream.
+pub fn record(seq_num: u64, headers: Vec<Header>, body: impl AsRef<[u8]>) -> SequencedRecord {
+    SequencedRecord {
+        seq_num,
+        headers,
+        body: Bytes::copy_from_slice(body.as_ref()),
+    }
+}
+
+/// A directory for the files of a test, removed when dropped so it's cleaned
+/// up even if the test fails.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("s2-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+
+    pub fn join(&self, path: impl AsRef<Path>) -> PathBuf {
+        self.0.join(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}